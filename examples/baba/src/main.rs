@@ -1,3 +1,5 @@
+#[cfg(feature = "golden")]
+use goodman::golden::{GoldenTest, ScriptedInput};
 use goodman::prelude::*;
 use level::Level;
 use other::{
@@ -15,16 +17,35 @@ fn main() {
     block_on(run());
 }
 
-async fn run() {
-    let event_loop = EventLoop::new();
-
-    let mut engine = EngineBuilder::new(WINDOW_SIZE)
+fn engine_builder() -> EngineBuilder {
+    EngineBuilder::new(WINDOW_SIZE)
         .with_target_fps(144)
         .use_near_filter_mode()
         // .show_engine_ui()
         .with_window_title("Baba".to_string())
-        .build(&event_loop)
-        .await;
+}
+
+async fn run() {
+    // run with --features golden -- --golden to compare the rendered frames against
+    // examples/baba/golden/baba.png
+    #[cfg(feature = "golden")]
+    if std::env::args().any(|arg| arg == "--golden") {
+        // walk baba two tiles to the right
+        let test = GoldenTest::new("baba", "examples/baba/golden")
+            .with_ticks(6)
+            .with_input(1, ScriptedInput::Press(Button::D))
+            .with_input(2, ScriptedInput::Release(Button::D))
+            .with_input(3, ScriptedInput::Press(Button::D))
+            .with_input(4, ScriptedInput::Release(Button::D));
+        if let Err(e) = test.check::<Game>(&mut engine_builder()).await {
+            panic!("{e}");
+        }
+        return;
+    }
+
+    let event_loop = EventLoop::new();
+
+    let mut engine = engine_builder().build(&event_loop).await;
 
     let game = Game::new(&mut engine);
 
@@ -44,15 +65,14 @@ impl Manager for Game {
     fn new(engine: &mut Engine) -> Self {
         // engine.use_sound(false);
 
-        // create background music 
-        let background_music = engine
-            .create_sound_source("examples/baba/src/assets/background.wav")
-            .unwrap();
-
-        // play background music and repeat when
-        engine
-            .play_sound(background_music.convert_samples().repeat_infinite())
-            .unwrap();
+        // create background music, the game runs without it if the file is missing
+        match engine.create_sound_source("examples/baba/src/assets/background.wav") {
+            // play background music and repeat when
+            Ok(background_music) => engine
+                .play_sound(background_music.convert_samples().repeat_infinite())
+                .unwrap(),
+            Err(e) => log::warn!("no background music: {e}"),
+        }
 
         let source = engine
             .create_sound_source("examples/baba/src/assets/pop.mp3")
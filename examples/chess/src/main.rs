@@ -1,6 +1,8 @@
 // WARNING: some of this code is really old and badly written, proceed with caution
 
 use crate::types::Kind;
+#[cfg(feature = "golden")]
+use goodman::golden::{GoldenTest, ScriptedInput};
 use goodman::prelude::*;
 use state::State;
 use textures::get_textures;
@@ -19,14 +21,33 @@ fn main() {
     block_on(run())
 }
 
-async fn run() {
-    let event_loop = EventLoop::new();
-    let mut engine = EngineBuilder::new(vec2(SCREENSIZE, SCREENSIZE))
+fn engine_builder() -> EngineBuilder {
+    EngineBuilder::new(vec2(SCREENSIZE, SCREENSIZE))
         // .show_engine_ui()
         .with_window_title("Chess".to_string())
         .with_target_fps(144)
-        .build(&event_loop)
-        .await;
+}
+
+async fn run() {
+    // run with --features golden -- --golden to compare the rendered frames against
+    // examples/chess/golden/chess.png
+    #[cfg(feature = "golden")]
+    if std::env::args().any(|arg| arg == "--golden") {
+        // select the white pawn in front of the king so its moves get shown
+        let pawn = vec2(4.5 * SQUARE as f64, 6.5 * SQUARE as f64);
+        let test = GoldenTest::new("chess", "examples/chess/golden")
+            .with_ticks(4)
+            .with_input(0, ScriptedInput::MoveCursor(pawn))
+            .with_input(1, ScriptedInput::Press(Button::LeftMouse))
+            .with_input(2, ScriptedInput::Release(Button::LeftMouse));
+        if let Err(e) = test.check::<Chess>(&mut engine_builder()).await {
+            panic!("{e}");
+        }
+        return;
+    }
+
+    let event_loop = EventLoop::new();
+    let mut engine = engine_builder().build(&event_loop).await;
 
     let chess = Chess::new(&mut engine);
     engine.start_loop(chess, event_loop)
@@ -67,8 +67,8 @@ impl State {
 fn get_clicked_square_coords(input: &Input) -> Option<(usize, usize)> {
     if input.is_button_pressed(Button::LeftMouse) {
         let coords = (
-            (input.get_cursor_pos().x as f32 / SQUARE) as usize,
-            (input.get_cursor_pos().y as f32 / SQUARE) as usize,
+            (input.get_cursor_world_pos().x as f32 / SQUARE) as usize,
+            (input.get_cursor_world_pos().y as f32 / SQUARE) as usize,
         );
         // println!("{:?}", coords);
         return Some(coords);
@@ -2,6 +2,8 @@
 // use left, right and middle mouse to place particles
 // scroll to make the area you place pixels in bigger or smaller
 
+#[cfg(feature = "golden")]
+use goodman::golden::{GoldenTest, ScriptedInput};
 use goodman::prelude::*;
 use particle::Particle;
 
@@ -22,11 +24,30 @@ fn main() {
     block_on(run())
 }
 
+fn engine_builder() -> EngineBuilder {
+    EngineBuilder::new(WINDOW_SIZE).with_target_fps(144)
+}
+
 async fn run() {
+    // run with --features golden -- --golden to compare the rendered frames against
+    // examples/particle_sim/golden/sim.png
+    #[cfg(feature = "golden")]
+    if std::env::args().any(|arg| arg == "--golden") {
+        // only wood gets placed because it doesn't move, sand and water move randomly
+        let test = GoldenTest::new("sim", "examples/particle_sim/golden")
+            .with_ticks(3)
+            .with_input(0, ScriptedInput::MoveCursor(vec2(600., 450.)))
+            .with_input(0, ScriptedInput::Press(Button::MiddleMouse))
+            .with_input(1, ScriptedInput::Release(Button::MiddleMouse));
+        if let Err(e) = test.check::<Simulation>(&mut engine_builder()).await {
+            panic!("{e}");
+        }
+        return;
+    }
+
     let event_loop = EventLoop::new();
-    let mut engine = EngineBuilder::new(WINDOW_SIZE)
+    let mut engine = engine_builder()
         .show_engine_ui()
-        .with_target_fps(144)
         .build(&event_loop)
         .await;
 
@@ -213,8 +234,8 @@ impl Simulation {
     }
 
     fn place_part_line(&mut self, input: &Input) {
-        let i = (input.get_cursor_pos().x / PART_SIZE.x as f64).floor() as usize;
-        let j = (input.get_cursor_pos().y / PART_SIZE.y as f64).floor() as usize;
+        let i = (input.get_cursor_world_pos().x / PART_SIZE.x as f64).floor() as usize;
+        let j = (input.get_cursor_world_pos().y / PART_SIZE.y as f64).floor() as usize;
 
         for c in 0..10 {
             let new_i = i + c - 5;
@@ -246,8 +267,8 @@ impl Simulation {
             vec.push((x_vec[i], y as isize))
         }
 
-        let i = (input.get_cursor_pos().x / PART_SIZE.x as f64).floor() as usize;
-        let j = (input.get_cursor_pos().y / PART_SIZE.y as f64).floor() as usize;
+        let i = (input.get_cursor_world_pos().x / PART_SIZE.x as f64).floor() as usize;
+        let j = (input.get_cursor_world_pos().y / PART_SIZE.y as f64).floor() as usize;
         // self.particles[j][i] = Particle::new(part_kind);
         // return;
 
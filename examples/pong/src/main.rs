@@ -1,3 +1,5 @@
+#[cfg(feature = "golden")]
+use goodman::golden::GoldenTest;
 use goodman::prelude::*;
 
 // paddle.rs and ball.rs don't contain any engine related code and don't do anything special
@@ -12,16 +14,28 @@ fn main() {
     block_on(run());
 }
 
-async fn run() {
-    let event_loop = EventLoop::new();
-
+fn engine_builder() -> EngineBuilder {
     let window_size = vec2(WINDOW_SIZE.x as f32, WINDOW_SIZE.y as f32);
-    let mut engine = EngineBuilder::new(window_size)
+    EngineBuilder::new(window_size)
         // .show_engine_ui()
         .with_target_fps(144)
         // .with_target_tps(100 * 1000)
-        .build(&event_loop)
-        .await;
+}
+
+async fn run() {
+    // run with --features golden -- --golden to compare the rendered frames against
+    // examples/pong/golden/pong.png
+    #[cfg(feature = "golden")]
+    if std::env::args().any(|arg| arg == "--golden") {
+        let test = GoldenTest::new("pong", "examples/pong/golden").with_ticks(60);
+        if let Err(e) = test.check::<Pong>(&mut engine_builder()).await {
+            panic!("{e}");
+        }
+        return;
+    }
+
+    let event_loop = EventLoop::new();
+    let mut engine = engine_builder().build(&event_loop).await;
 
     let pong = Pong::new(&mut engine);
 
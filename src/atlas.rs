@@ -0,0 +1,125 @@
+use image::{GenericImage, RgbaImage};
+
+use crate::math::{rect32, Rect32};
+use crate::texture::TextureError;
+
+// transparent pixels between images, one of them repeats the edge of the image so linear filtering doesn't bleed
+// at full size, atlases get no mipmaps because smaller versions would still blend the images together
+const PADDING: u32 = 2;
+
+/// Where an image ended up after packing.
+pub(crate) struct Placement {
+    pub page: usize,
+    pub region: Rect32,
+}
+
+/// Packs `images` into as few pages of at most `max_size` x `max_size` pixels as possible, using rows of
+/// images sorted by height. Returns the pages and the placement of every image in the order of `images`.
+pub(crate) fn pack(
+    images: &[RgbaImage],
+    max_size: u32,
+) -> Result<(Vec<RgbaImage>, Vec<Placement>), TextureError> {
+    if let Some(img) = images
+        .iter()
+        .find(|img| img.width() + 2 * PADDING > max_size || img.height() + 2 * PADDING > max_size)
+    {
+        return Err(TextureError::Dimensions {
+            width: img.width(),
+            height: img.height(),
+            max: max_size - 2 * PADDING,
+        });
+    }
+
+    if images.is_empty() {
+        return Ok((vec![], vec![]));
+    }
+
+    // Make the pages square and just big enough to fit all images, unless that's bigger than max_size
+    let area: u64 = images
+        .iter()
+        .map(|img| ((img.width() + 2 * PADDING) * (img.height() + 2 * PADDING)) as u64)
+        .sum();
+    let widest = images.iter().map(|img| img.width() + 2 * PADDING).max();
+    let page_width = ((area as f64 * 1.2).sqrt() as u32)
+        .max(widest.unwrap_or(1))
+        .next_power_of_two()
+        .min(max_size);
+
+    let mut order: Vec<usize> = (0..images.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(images[i].height()));
+
+    // (x, y) of the next image and the height of the current row, for every page
+    let mut cursors: Vec<(u32, u32, u32)> = vec![(0, 0, 0)];
+    let mut positions = vec![(0, 0, 0); images.len()];
+    for i in order {
+        let (w, h) = (
+            images[i].width() + 2 * PADDING,
+            images[i].height() + 2 * PADDING,
+        );
+        let cursor = cursors.last_mut().unwrap();
+        if cursor.0 + w > page_width {
+            *cursor = (0, cursor.1 + cursor.2, 0);
+        }
+        if cursor.1 + h > max_size {
+            cursors.push((0, 0, 0));
+        }
+
+        let page = cursors.len() - 1;
+        let cursor = &mut cursors[page];
+        positions[i] = (page, cursor.0, cursor.1);
+        cursor.0 += w;
+        cursor.2 = cursor.2.max(h);
+    }
+
+    // Pages are only as high as their rows
+    let mut pages: Vec<RgbaImage> = cursors
+        .iter()
+        .map(|&(_, y, row_height)| RgbaImage::new(page_width, y + row_height))
+        .collect();
+
+    let mut placements = vec![];
+    for (img, &(page, x, y)) in images.iter().zip(&positions) {
+        let (x, y) = (x + PADDING, y + PADDING);
+        copy_extruded(&mut pages[page], img, x, y);
+        placements.push(Placement {
+            page,
+            region: rect32(x as f32, y as f32, img.width() as f32, img.height() as f32),
+        });
+    }
+    Ok((pages, placements))
+}
+
+// copies img to (x, y) of page and repeats its outermost pixels once around it
+fn copy_extruded(page: &mut RgbaImage, img: &RgbaImage, x: u32, y: u32) {
+    let (w, h) = img.dimensions();
+    if w == 0 || h == 0 {
+        return;
+    }
+    page.copy_from(img, x, y)
+        .expect("packed images lie within their page");
+
+    for ix in 0..w {
+        page.put_pixel(x + ix, y - 1, *img.get_pixel(ix, 0));
+        page.put_pixel(x + ix, y + h, *img.get_pixel(ix, h - 1));
+    }
+    for iy in 0..h {
+        page.put_pixel(x - 1, y + iy, *img.get_pixel(0, iy));
+        page.put_pixel(x + w, y + iy, *img.get_pixel(w - 1, iy));
+    }
+    let corners = [
+        (0, 0, x - 1, y - 1),
+        (w - 1, 0, x + w, y - 1),
+        (0, h - 1, x - 1, y + h),
+        (w - 1, h - 1, x + w, y + h),
+    ];
+    for (ix, iy, px, py) in corners {
+        page.put_pixel(px, py, *img.get_pixel(ix, iy));
+    }
+}
+
+pub(crate) fn decode(images: &[&[u8]]) -> Result<Vec<RgbaImage>, TextureError> {
+    images
+        .iter()
+        .map(|bytes| Ok(image::load_from_memory(bytes)?.to_rgba8()))
+        .collect()
+}
@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use image::{DynamicImage, GenericImageView, RgbaImage};
+
+use crate::texture::TextureError;
+
+/// A post effect that comes with the engine, with its parameters. Create one with `Engine::create_builtin_effect`
+/// or `EngineBuilder::with_post_effect`, and change its parameters with `Engine::set_builtin_effect_params`.
+#[derive(Debug, Clone)]
+pub enum BuiltinEffect {
+    Crt(CrtParams),
+    Pixelate(PixelateParams),
+    Bloom(BloomParams),
+    ColorFilter(ColorFilterParams),
+    Colorblind(ColorblindParams),
+    Lut(LutParams),
+}
+impl BuiltinEffect {
+    pub(crate) fn get_name(&self) -> &'static str {
+        match self {
+            BuiltinEffect::Crt(_) => "CRT",
+            BuiltinEffect::Pixelate(_) => "pixelate",
+            BuiltinEffect::Bloom(_) => "bloom",
+            BuiltinEffect::ColorFilter(_) => "color filter",
+            BuiltinEffect::Colorblind(_) => "colorblind",
+            BuiltinEffect::Lut(_) => "LUT",
+        }
+    }
+
+    pub(crate) fn get_source(&self) -> &'static str {
+        match self {
+            BuiltinEffect::Crt(_) => include_str!("effects/crt.wgsl"),
+            BuiltinEffect::Pixelate(_) => include_str!("effects/pixelate.wgsl"),
+            BuiltinEffect::Bloom(_) => include_str!("effects/bloom.wgsl"),
+            BuiltinEffect::ColorFilter(_) | BuiltinEffect::Colorblind(_) => {
+                include_str!("effects/color_matrix.wgsl")
+            }
+            BuiltinEffect::Lut(_) => include_str!("effects/lut.wgsl"),
+        }
+    }
+
+    /// The values `get_param` returns in the shader of the effect.
+    pub(crate) fn to_params(&self) -> Vec<f32> {
+        match self {
+            BuiltinEffect::Crt(p) => vec![
+                p.curvature,
+                p.scanline_intensity,
+                p.scanline_size,
+                p.vignette,
+            ],
+            BuiltinEffect::Pixelate(p) => vec![p.pixel_size],
+            BuiltinEffect::Bloom(p) => vec![p.threshold, p.intensity, p.radius],
+            BuiltinEffect::ColorFilter(p) => color_matrix_params(p.filter.get_matrix(), p.strength),
+            BuiltinEffect::Colorblind(p) => color_matrix_params(p.mode.get_matrix(), p.strength),
+            BuiltinEffect::Lut(p) => vec![p.strength],
+        }
+    }
+}
+
+fn color_matrix_params(matrix: [[f32; 3]; 3], strength: f32) -> Vec<f32> {
+    let mut params: Vec<f32> = matrix.iter().flatten().copied().collect();
+    params.push(strength);
+    params
+}
+
+/// Curved screen, scanlines and darkened corners of an old CRT monitor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrtParams {
+    /// How much the screen bulges outwards, 0 keeps it flat
+    pub curvature: f32,
+    /// How dark the gaps between scanlines are, from 0 to 1
+    pub scanline_intensity: f32,
+    /// The height of a scanline in pixels of the window
+    pub scanline_size: f32,
+    /// How dark the corners get, from 0 to 1
+    pub vignette: f32,
+}
+impl Default for CrtParams {
+    fn default() -> Self {
+        Self {
+            curvature: 0.08,
+            scanline_intensity: 0.3,
+            scanline_size: 3.,
+            vignette: 0.3,
+        }
+    }
+}
+
+/// Draws the screen with big blocky pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelateParams {
+    /// The size of a block in pixels of the window
+    pub pixel_size: f32,
+}
+impl PixelateParams {
+    pub fn from_pixel_size(pixel_size: f32) -> Self {
+        Self { pixel_size }
+    }
+}
+impl Default for PixelateParams {
+    fn default() -> Self {
+        Self { pixel_size: 4. }
+    }
+}
+
+/// Makes bright parts of the screen glow.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomParams {
+    /// The brightness from 0 to 1 above which colors glow
+    pub threshold: f32,
+    pub intensity: f32,
+    /// How far the glow reaches in pixels of the window
+    pub radius: f32,
+}
+impl Default for BloomParams {
+    fn default() -> Self {
+        Self {
+            threshold: 0.7,
+            intensity: 0.8,
+            radius: 12.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorFilter {
+    #[default]
+    Grayscale,
+    Sepia,
+}
+impl ColorFilter {
+    fn get_matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            ColorFilter::Grayscale => [[0.2126, 0.7152, 0.0722]; 3],
+            ColorFilter::Sepia => [
+                [0.393, 0.769, 0.189],
+                [0.349, 0.686, 0.168],
+                [0.272, 0.534, 0.131],
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorFilterParams {
+    pub filter: ColorFilter,
+    /// How much of the filtered color is used, from 0 to 1
+    pub strength: f32,
+}
+impl ColorFilterParams {
+    pub fn from_filter(filter: ColorFilter) -> Self {
+        Self {
+            filter,
+            ..Default::default()
+        }
+    }
+}
+impl Default for ColorFilterParams {
+    fn default() -> Self {
+        Self {
+            filter: ColorFilter::default(),
+            strength: 1.,
+        }
+    }
+}
+
+/// The kind of color blindness to simulate, to check whether a game stays readable with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorblindMode {
+    /// No red cones
+    #[default]
+    Protanopia,
+    /// No green cones
+    Deuteranopia,
+    /// No blue cones
+    Tritanopia,
+    /// No color vision at all
+    Achromatopsia,
+}
+impl ColorblindMode {
+    // The matrices of Machado et al. 2009 for full severity, made for linear colors
+    fn get_matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            ColorblindMode::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            ColorblindMode::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            ColorblindMode::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+            ColorblindMode::Achromatopsia => ColorFilter::Grayscale.get_matrix(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorblindParams {
+    pub mode: ColorblindMode,
+    /// How much of the simulated color is used, from 0 to 1
+    pub strength: f32,
+}
+impl ColorblindParams {
+    pub fn from_mode(mode: ColorblindMode) -> Self {
+        Self {
+            mode,
+            ..Default::default()
+        }
+    }
+}
+impl Default for ColorblindParams {
+    fn default() -> Self {
+        Self {
+            mode: ColorblindMode::default(),
+            strength: 1.,
+        }
+    }
+}
+
+/// A 3D color lookup table for color grading, loaded from a strip of n squares of n*n pixels side by side.
+/// The square picks the blue channel of a color, x within the square the red one and y the green one.
+/// Clones share the pixels.
+#[derive(Clone)]
+pub struct Lut {
+    strip: Arc<RgbaImage>,
+}
+impl std::fmt::Debug for Lut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Lut")
+            .field("size", &self.get_size())
+            .finish()
+    }
+}
+impl Lut {
+    /// Loads the strip from an encoded image, like the bytes of a png file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TextureError> {
+        Self::from_image(&image::load_from_memory(bytes)?)
+    }
+    pub fn from_image(img: &DynamicImage) -> Result<Self, TextureError> {
+        let (width, height) = img.dimensions();
+        if !(2..=256).contains(&height) || width != height * height {
+            return Err(TextureError::LutSize { width, height });
+        }
+        Ok(Self {
+            strip: Arc::new(img.to_rgba8()),
+        })
+    }
+
+    /// The amount of entries of the table for every channel.
+    pub fn get_size(&self) -> u32 {
+        self.strip.height()
+    }
+
+    pub(crate) fn is_same(&self, other: &Lut) -> bool {
+        Arc::ptr_eq(&self.strip, &other.strip)
+    }
+
+    /// Uploads the strip into a 3D texture, with a square of the strip for every layer.
+    pub(crate) fn create_view(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> wgpu::TextureView {
+        let n = self.get_size();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("LUT Texture"),
+            size: wgpu::Extent3d {
+                width: n,
+                height: n,
+                depth_or_array_layers: n,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            // the colors of the table are read as linear colors, the effect converts them to the ones of the scene
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        for layer in 0..n {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer,
+                    },
+                },
+                &self.strip,
+                wgpu::ImageDataLayout {
+                    // the rows of a square lie within the rows of the whole strip
+                    offset: 4 * (layer * n) as u64,
+                    bytes_per_row: Some(4 * n * n),
+                    rows_per_image: Some(n),
+                },
+                wgpu::Extent3d {
+                    width: n,
+                    height: n,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LutParams {
+    pub lut: Lut,
+    /// How much of the graded color is used, from 0 to 1
+    pub strength: f32,
+}
+impl LutParams {
+    pub fn from_lut(lut: Lut) -> Self {
+        Self { lut, strength: 1. }
+    }
+}
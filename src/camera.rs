@@ -1,48 +1,329 @@
+use cgmath::{vec2, vec3, vec4, Deg, Matrix4, SquareMatrix};
 use wgpu::{util::DeviceExt, Device};
 
-use crate::{input::Input, prelude::Button};
+use crate::{
+    input::Input,
+    prelude::{rect32, Button, Rect32, Vec32},
+};
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
-    pos: [f32; 2],
+    view_proj: [[f32; 4]; 4],
 }
 impl CameraUniform {
     pub fn new() -> Self {
-        Self { pos: [0., 0.] }
+        Self {
+            view_proj: Matrix4::identity().into(),
+        }
     }
 }
 
+struct Shake {
+    // between 0 and 1, the shake intensity is trauma squared
+    trauma: f32,
+    // amount of trauma removed per second
+    decay: f32,
+    max_offset: f32,
+    // in degrees
+    max_rotation: f32,
+    frequency: f32,
+    time: f32,
+}
+impl Shake {
+    fn new() -> Self {
+        Self {
+            trauma: 0.,
+            decay: 1.,
+            max_offset: 20.,
+            max_rotation: 3.,
+            frequency: 15.,
+            time: 0.,
+        }
+    }
+    // Smooth noise between -1 and 1, sums of sines are enough for a shake
+    fn noise(&self, seed: f32) -> f32 {
+        let t = self.time * self.frequency;
+        ((t + seed).sin() + (t * 2.3 + seed * 1.7).sin()) * 0.5
+    }
+    fn get_offset(&self) -> Vec32 {
+        let intensity = self.trauma * self.trauma;
+        vec2(self.noise(1.), self.noise(17.)) * self.max_offset * intensity
+    }
+    fn get_rotation(&self) -> f32 {
+        self.trauma * self.trauma * self.max_rotation * self.noise(31.)
+    }
+}
+
+/// A 2D camera, world coordinates are in pixels with the y-axis pointing down.
 pub struct Camera {
-    pub uniform: CameraUniform,
-    movement_enabled: bool,
+    // world position shown at the center of the viewport
+    pos: Vec32,
+    zoom: f32,
+    // in degrees
+    rotation: f32,
+    // amount of world units visible at a zoom of 1
+    size: Vec32,
+    // area of the window that gets rendered to, None means the whole window
+    viewport: Option<Rect32>,
+    movement_speed: Option<f32>,
+    follow_target: Option<Vec32>,
+    // roughly the time in seconds it takes to move 63% of the way to the target, 0 snaps to it
+    follow_damping: f32,
+    // size of the area around the center in which the target can move without the camera following
+    dead_zone: Vec32,
+    // area of the world the visible rect has to stay within
+    bounds: Option<Rect32>,
+    shake: Shake,
+    uniform_outdated: bool,
 }
 impl Camera {
-    const SPEED: f32 = 0.0001;
-    pub fn new(movement_enabled: bool) -> Self {
+    pub(crate) fn new(size: Vec32) -> Self {
         Self {
-            movement_enabled,
-            uniform: CameraUniform::new(),
+            pos: size * 0.5,
+            zoom: 1.,
+            rotation: 0.,
+            size,
+            viewport: None,
+            movement_speed: None,
+            follow_target: None,
+            follow_damping: 0.,
+            dead_zone: vec2(0., 0.),
+            bounds: None,
+            shake: Shake::new(),
+            uniform_outdated: true,
+        }
+    }
+
+    /// Moves the camera with WASD at `speed` world units per second, None disables it.
+    pub fn set_movement_speed(&mut self, speed: Option<f32>) {
+        self.movement_speed = speed;
+    }
+
+    pub fn get_pos(&self) -> Vec32 {
+        self.pos
+    }
+    pub fn set_pos(&mut self, pos: Vec32) {
+        self.pos = pos;
+        self.uniform_outdated = true;
+    }
+    pub fn move_by(&mut self, offset: Vec32) {
+        self.set_pos(self.pos + offset);
+    }
+
+    pub fn get_zoom(&self) -> f32 {
+        self.zoom
+    }
+    /// A zoom of 2 makes everything twice as big.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom;
+        self.uniform_outdated = true;
+    }
+
+    pub fn get_rotation(&self) -> f32 {
+        self.rotation
+    }
+    /// Rotates the view around its center, in degrees.
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation;
+        self.uniform_outdated = true;
+    }
+
+    pub fn get_size(&self) -> Vec32 {
+        self.size
+    }
+    /// Sets the amount of world units that are visible at a zoom of 1.
+    pub fn set_size(&mut self, size: Vec32) {
+        self.size = size;
+        self.uniform_outdated = true;
+    }
+
+    pub fn get_viewport(&self) -> Option<Rect32> {
+        self.viewport
+    }
+    /// Sets the area of the window in pixels the camera renders to, None means the whole window.
+    pub fn set_viewport(&mut self, viewport: Option<Rect32>) {
+        self.viewport = viewport;
+    }
+
+    /// Makes the camera follow `target` every tick, call it again whenever the target moves.
+    pub fn follow(&mut self, target: Vec32) {
+        self.follow_target = Some(target);
+    }
+    pub fn stop_following(&mut self) {
+        self.follow_target = None;
+    }
+    /// Roughly the time in seconds it takes to move 63% of the way to the target, 0 snaps to it.
+    pub fn set_follow_damping(&mut self, damping: f32) {
+        self.follow_damping = damping.max(0.);
+    }
+    /// The target can move within an area of `size` around the center without the camera following.
+    pub fn set_dead_zone(&mut self, size: Vec32) {
+        self.dead_zone = size;
+    }
+
+    /// Keeps the visible area of the camera within `bounds`, None removes the bounds.
+    pub fn set_bounds(&mut self, bounds: Option<Rect32>) {
+        self.bounds = bounds;
+    }
+
+    /// Adds trauma between 0 and 1 that makes the screen shake, the shake gets stronger with more trauma.
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.shake.trauma = (self.shake.trauma + trauma).clamp(0., 1.);
+    }
+    pub fn get_trauma(&self) -> f32 {
+        self.shake.trauma
+    }
+    /// Sets the offset in world units and rotation in degrees at full trauma,
+    /// and the amount of trauma that gets removed every second.
+    pub fn set_shake(&mut self, max_offset: f32, max_rotation: f32, decay: f32) {
+        self.shake.max_offset = max_offset;
+        self.shake.max_rotation = max_rotation;
+        self.shake.decay = decay;
+    }
+
+    /// Returns the area of the world that is visible when the camera isn't rotated.
+    pub fn get_visible_rect(&self) -> Rect32 {
+        let visible_size = self.size / self.zoom;
+        Rect32::new(self.pos - visible_size * 0.5, visible_size)
+    }
+
+    /// Converts a position in pixels of the render target of size `target_size` to a position in the world.
+    pub(crate) fn screen_to_world(&self, screen_pos: Vec32, target_size: Vec32) -> Vec32 {
+        let v = self.get_viewport_or_full(target_size);
+        let ndc = vec4(
+            (screen_pos.x - v.x) / v.w * 2. - 1.,
+            1. - (screen_pos.y - v.y) / v.h * 2.,
+            0.,
+            1.,
+        );
+        // The view projection can only be singular with a zoom of 0, in which case nothing is visible anyway
+        let inv_view_proj = self.get_view_proj().invert().unwrap_or(Matrix4::identity());
+        let world_pos = inv_view_proj * ndc;
+        vec2(world_pos.x, world_pos.y)
+    }
+
+    /// Converts a position in the world to a position in pixels of the render target of size `target_size`.
+    pub(crate) fn world_to_screen(&self, world_pos: Vec32, target_size: Vec32) -> Vec32 {
+        let v = self.get_viewport_or_full(target_size);
+        let ndc = self.get_view_proj() * vec4(world_pos.x, world_pos.y, 0., 1.);
+        vec2(
+            v.x + (ndc.x + 1.) * 0.5 * v.w,
+            v.y + (1. - ndc.y) * 0.5 * v.h,
+        )
+    }
+
+    fn get_viewport_or_full(&self, target_size: Vec32) -> Rect32 {
+        self.viewport
+            .unwrap_or(rect32(0., 0., target_size.x, target_size.y))
+    }
+
+    pub(crate) fn get_view_proj(&self) -> Matrix4<f32> {
+        let proj = Matrix4::from_nonuniform_scale(2. / self.size.x, -2. / self.size.y, 1.);
+        let pos = self.pos + self.shake.get_offset();
+        let view = Matrix4::from_angle_z(Deg(-self.rotation - self.shake.get_rotation()))
+            * Matrix4::from_scale(self.zoom)
+            * Matrix4::from_translation(vec3(-pos.x, -pos.y, 0.));
+        proj * view
+    }
+
+    pub(crate) fn update(&mut self, input: &Input, delta_t: f64) {
+        let delta_t = delta_t as f32;
+
+        self.update_movement(input, delta_t);
+        self.update_follow(delta_t);
+        self.clamp_to_bounds();
+
+        if self.shake.trauma > 0. {
+            self.shake.time += delta_t;
+            self.shake.trauma = (self.shake.trauma - self.shake.decay * delta_t).max(0.);
+            self.uniform_outdated = true;
         }
     }
-    pub fn update(&mut self, input: &Input) -> bool {
-        if !self.movement_enabled {
-            false
-        } else if input.is_button_held(Button::D) {
-            self.uniform.pos[0] += Camera::SPEED;
-            true
-        } else if input.is_button_held(Button::A) {
-            self.uniform.pos[0] -= Camera::SPEED;
-            true
-        } else if input.is_button_held(Button::W) {
-            self.uniform.pos[1] += Camera::SPEED;
-            true
-        } else if input.is_button_held(Button::S) {
-            self.uniform.pos[1] -= Camera::SPEED;
-            true
+
+    fn update_movement(&mut self, input: &Input, delta_t: f32) {
+        let speed = match self.movement_speed {
+            Some(speed) => speed * delta_t / self.zoom,
+            None => return,
+        };
+
+        let mut dir = vec2(0., 0.);
+        if input.is_button_held(Button::D) {
+            dir.x += 1.;
+        }
+        if input.is_button_held(Button::A) {
+            dir.x -= 1.;
+        }
+        if input.is_button_held(Button::W) {
+            dir.y -= 1.;
+        }
+        if input.is_button_held(Button::S) {
+            dir.y += 1.;
+        }
+
+        if dir != vec2(0., 0.) {
+            self.move_by(dir * speed);
+        }
+    }
+
+    fn update_follow(&mut self, delta_t: f32) {
+        let target = match self.follow_target {
+            Some(target) => target,
+            None => return,
+        };
+
+        // Only move far enough for the target to be at the edge of the dead zone
+        let half_dead_zone = self.dead_zone * 0.5;
+        let diff = target - self.pos;
+        let excess = vec2(
+            diff.x - diff.x.clamp(-half_dead_zone.x, half_dead_zone.x),
+            diff.y - diff.y.clamp(-half_dead_zone.y, half_dead_zone.y),
+        );
+        if excess == vec2(0., 0.) {
+            return;
+        }
+
+        let t = if self.follow_damping == 0. {
+            1.
         } else {
-            false
+            1. - (-delta_t / self.follow_damping).exp()
+        };
+        self.move_by(excess * t);
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+
+        // Center on an axis if the bounds are smaller than the visible area
+        let half_visible = self.size / self.zoom * 0.5;
+        let clamp_axis = |pos: f32, min: f32, size: f32, half_visible: f32| {
+            if size <= half_visible * 2. {
+                min + size * 0.5
+            } else {
+                pos.clamp(min + half_visible, min + size - half_visible)
+            }
+        };
+        let clamped = vec2(
+            clamp_axis(self.pos.x, bounds.x, bounds.w, half_visible.x),
+            clamp_axis(self.pos.y, bounds.y, bounds.h, half_visible.y),
+        );
+        if clamped != self.pos {
+            self.set_pos(clamped);
+        }
+    }
+
+    /// Returns the new uniform if the camera changed since the last call.
+    pub(crate) fn take_uniform(&mut self) -> Option<CameraUniform> {
+        if !self.uniform_outdated {
+            return None;
         }
+        self.uniform_outdated = false;
+        Some(CameraUniform {
+            view_proj: self.get_view_proj().into(),
+        })
     }
 }
 
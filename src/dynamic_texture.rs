@@ -0,0 +1,130 @@
+use image::{GenericImage, GenericImageView, Rgba, RgbaImage};
+
+use crate::minor_types::Color;
+use crate::texture::Texture;
+
+/// A texture with its pixels kept on the CPU, so they can be changed every frame. Changes only reach the
+/// GPU with `Engine::upload_dynamic_texture`, which uploads the part that changed since the last upload.
+pub struct DynamicTexture {
+    texture: Texture,
+    pixels: RgbaImage,
+    // (min x, min y, max x, max y) of the changed pixels, max exclusive
+    dirty: Option<(u32, u32, u32, u32)>,
+}
+impl DynamicTexture {
+    pub(crate) fn new(texture: Texture, pixels: RgbaImage) -> Self {
+        Self {
+            texture,
+            pixels,
+            dirty: None,
+        }
+    }
+
+    /// The texture to draw, it shows the pixels of the last upload.
+    pub fn get_texture(&self) -> &Texture {
+        &self.texture
+    }
+    pub fn get_width(&self) -> u32 {
+        self.pixels.width()
+    }
+    pub fn get_height(&self) -> u32 {
+        self.pixels.height()
+    }
+
+    /// Pixels outside of the texture are ignored.
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        if self.pixels.in_bounds(x, y) {
+            self.pixels.put_pixel(x, y, Rgba(color.to_rgba8()));
+            self.mark_dirty(x, y, 1, 1);
+        }
+    }
+    /// Returns None for pixels outside of the texture.
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<Color> {
+        if self.pixels.in_bounds(x, y) {
+            Some(Color::from_rgba8(self.pixels.get_pixel(x, y).0))
+        } else {
+            None
+        }
+    }
+
+    /// Sets the pixels of a rect, the part outside of the texture is ignored.
+    pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Color) {
+        let (width, height) = self.clip(x, y, width, height);
+        let color = Rgba(color.to_rgba8());
+        for py in y..y + height {
+            for px in x..x + width {
+                self.pixels.put_pixel(px, py, color);
+            }
+        }
+        self.mark_dirty(x, y, width, height);
+    }
+    pub fn fill(&mut self, color: Color) {
+        self.fill_rect(0, 0, self.get_width(), self.get_height(), color);
+    }
+
+    /// Copies `img` with its top left at (`x`, `y`), the part outside of the texture is ignored.
+    pub fn set_region(&mut self, x: u32, y: u32, img: &RgbaImage) {
+        let (width, height) = self.clip(x, y, img.width(), img.height());
+        let view = img.view(0, 0, width, height);
+        self.pixels
+            .copy_from(&*view, x, y)
+            .expect("the region was clipped to the texture");
+        self.mark_dirty(x, y, width, height);
+    }
+
+    /// All pixels, as they are on the CPU.
+    pub fn get_pixels(&self) -> &RgbaImage {
+        &self.pixels
+    }
+
+    // the size of the part of the rect that lies within the texture
+    fn clip(&self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        (
+            width.min(self.get_width().saturating_sub(x)),
+            height.min(self.get_height().saturating_sub(y)),
+        )
+    }
+
+    fn mark_dirty(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let (x1, y1) = (x + width, y + height);
+        self.dirty = Some(match self.dirty {
+            Some((dx0, dy0, dx1, dy1)) => (dx0.min(x), dy0.min(y), dx1.max(x1), dy1.max(y1)),
+            None => (x, y, x1, y1),
+        });
+    }
+
+    /// Writes the changed pixels to the GPU texture.
+    pub(crate) fn upload(&mut self, queue: &wgpu::Queue) {
+        let Some((x0, y0, x1, y1)) = self.dirty.take() else {
+            return;
+        };
+        if self.texture.is_unloaded() {
+            return;
+        }
+
+        let width = self.pixels.width();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: x0, y: y0, z: 0 },
+            },
+            &self.pixels,
+            wgpu::ImageDataLayout {
+                // the rows of the region lie within the rows of the whole image
+                offset: 4 * (y0 as u64 * width as u64 + x0 as u64),
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(y1 - y0),
+            },
+            wgpu::Extent3d {
+                width: x1 - x0,
+                height: y1 - y0,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
@@ -0,0 +1,25 @@
+// params: threshold, intensity, radius in pixels
+const BLOOM_SAMPLES: i32 = 32;
+
+fn effect(uv: vec2<f32>, color: vec4<f32>) -> vec4<f32> {
+    let threshold = get_param(0u);
+    let radius = get_param(2u);
+    let texel = 1. / get_scene_size();
+
+    // the bright parts of a disk around the pixel, spread out along a spiral
+    var glow = vec3<f32>(0.);
+    var total = 0.;
+    for (var i = 0; i < BLOOM_SAMPLES; i++) {
+        let t = (f32(i) + 0.5) / f32(BLOOM_SAMPLES);
+        let angle = f32(i) * 2.3999632;
+        let offset = vec2<f32>(cos(angle), sin(angle)) * sqrt(t) * radius * texel;
+        // in linear colors, so the same pixels glow whatever the format of the frame
+        let c = scene_to_linear(textureSampleLevel(scene, scene_sampler, uv + offset, 0.).rgb);
+        let luminance = dot(c, vec3<f32>(0.2126, 0.7152, 0.0722));
+        let weight = 1. - t * 0.5;
+        glow += c * max(luminance - threshold, 0.) / max(luminance, 0.0001) * weight;
+        total += weight;
+    }
+    let lit = scene_to_linear(color.rgb) + glow / total * get_param(1u);
+    return vec4<f32>(linear_to_scene(lit), color.a);
+}
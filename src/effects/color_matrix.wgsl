@@ -0,0 +1,12 @@
+// params: a 3x3 matrix row by row, strength
+fn effect(uv: vec2<f32>, color: vec4<f32>) -> vec4<f32> {
+    // the matrices are made for linear colors
+    let c = scene_to_linear(color.rgb);
+    let filtered = vec3<f32>(
+        dot(c, vec3<f32>(get_param(0u), get_param(1u), get_param(2u))),
+        dot(c, vec3<f32>(get_param(3u), get_param(4u), get_param(5u))),
+        dot(c, vec3<f32>(get_param(6u), get_param(7u), get_param(8u))),
+    );
+    let mixed = mix(c, clamp(filtered, vec3<f32>(0.), vec3<f32>(1.)), get_param(9u));
+    return vec4<f32>(linear_to_scene(mixed), color.a);
+}
@@ -0,0 +1,18 @@
+// params: curvature, scanline intensity, scanline size in pixels, vignette
+fn effect(uv: vec2<f32>, color: vec4<f32>) -> vec4<f32> {
+    // bend the screen outwards like the glass of a tube
+    let centered = uv * 2. - 1.;
+    let warped = centered + centered * centered.yx * centered.yx * get_param(0u);
+    let warped_uv = warped * 0.5 + 0.5;
+    // sampling has to happen before leaving the screen is checked
+    let warped_color = sample_scene(warped_uv);
+
+    let scanline_size = max(get_param(2u), 1.);
+    let line = 0.5 + 0.5 * cos(warped_uv.y * get_scene_size().y / scanline_size * 6.2831853);
+    let scanlines = mix(1., line, get_param(1u));
+    let vignette = clamp(1. - get_param(3u) * dot(warped, warped) * 0.5, 0., 1.);
+
+    let inside = all(warped_uv >= vec2<f32>(0.)) && all(warped_uv <= vec2<f32>(1.));
+    let rgb = select(vec3<f32>(0.), warped_color.rgb * scanlines * vignette, inside);
+    return vec4<f32>(rgb, 1.);
+}
@@ -0,0 +1,16 @@
+// params: strength
+@group(2) @binding(0)
+var lut_sampler: sampler;
+@group(2) @binding(1)
+var lut: texture_3d<f32>;
+
+fn effect(uv: vec2<f32>, color: vec4<f32>) -> vec4<f32> {
+    let n = f32(textureDimensions(lut).x);
+    // LUTs are made for colors as they are stored in images
+    let stored = linear_to_srgb(scene_to_linear(color.rgb));
+    // the centers of the first and last texel map to 0 and 1
+    let coords = clamp(stored, vec3<f32>(0.), vec3<f32>(1.)) * (n - 1.) / n + 0.5 / n;
+    // the table is an sRGB texture, so it's read as linear colors
+    let graded = linear_to_scene(textureSample(lut, lut_sampler, coords).rgb);
+    return vec4<f32>(mix(color.rgb, graded, get_param(0u)), color.a);
+}
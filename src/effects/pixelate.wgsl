@@ -0,0 +1,8 @@
+// params: pixel size in pixels of the screen
+fn effect(uv: vec2<f32>, color: vec4<f32>) -> vec4<f32> {
+    let size = get_scene_size();
+    let pixel_size = max(get_param(0u), 1.);
+    // the first pixel of the block the pixel lies in, loaded as is so the block doesn't blend its neighbours
+    let block = floor(uv * size / pixel_size) * pixel_size;
+    return textureLoad(scene, min(vec2<i32>(block), vec2<i32>(size) - 1), 0);
+}
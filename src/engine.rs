@@ -1,19 +1,50 @@
-use wgpu::{BindGroup, Buffer};
-use winit::{event::Event, event_loop::EventLoop, window::Window};
+use cgmath::vec2;
+use std::mem::size_of;
+use wgpu::Buffer;
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::EventLoop,
+    window::Window,
+};
 
 use crate::{
-    camera::Camera,
-    input::Input,
-    math::Rect32,
+    input::{ButtonEnum, Input},
+    layer::Layer,
+    material::Materials,
+    math::{rect32, Rect32},
     minor_types::{DrawParams, Manager},
+    post_effect::PostEffects,
     prelude::Sound,
-    texture::Texture,
+    render_target::LayerTarget,
+    text::GlyphCache,
+    texture::{Texture, TextureBinding},
+    texture_registry::{TextureRegistry, WHITE_PIXEL_INDEX},
     time::TimeManager,
     ui::Ui,
-    vert_buffers::{self, Instance, TexCoords},
+    upscale::Upscaler,
+    vert_buffers::{self, Draw, Instance, TexCoords},
 };
 
 mod engine_manager;
+mod materials;
+mod post_effects;
+mod render_targets;
+mod shapes;
+mod text;
+mod textures;
+
+/// What the engine renders into, either a window's surface or an offscreen texture.
+pub(crate) enum Canvas {
+    Window {
+        window: Window,
+        surface: wgpu::Surface,
+    },
+    Headless {
+        texture: wgpu::Texture,
+        // whether run_headless already started the manager
+        started: bool,
+    },
+}
 
 pub struct Engine {
     input: Input,
@@ -21,33 +52,39 @@ pub struct Engine {
     ui: Ui,
     sound: Sound,
 
-    window: Window,
+    canvas: Canvas,
     win_size: winit::dpi::PhysicalSize<u32>,
     win_background_color: wgpu::Color,
-    win_bind_group: BindGroup,
 
-    surface: wgpu::Surface,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
 
-    render_pipeline: wgpu::RenderPipeline,
     vertex_buffer: Buffer,
     index_buffer: Buffer,
     tex_coords_buffer: Buffer,
 
     instances: Vec<Instance>,
     tex_coords: Vec<TexCoords>,
+    // the material of every instance, kept out of the instance buffer since the shader doesn't need it
+    material_ids: Vec<u32>,
     instances_rendered: usize,
     instance_buffer: Buffer,
 
-    tex_bind: Option<BindGroup>,
-    texture_amt_created: u32,
+    frame_capture: Option<wgpu::Texture>,
+    screenshot_key: Option<ButtonEnum>,
+
+    textures: TextureRegistry,
     use_near_filter_mode: bool,
+    glyph_cache: GlyphCache,
+    materials: Materials,
+    post_effects: PostEffects,
+    // only with a virtual resolution
+    upscaler: Option<Upscaler>,
 
-    camera: Camera,
-    camera_bind_group: BindGroup,
-    camera_buffer: Buffer,
+    layers: Vec<Layer>,
+    // layer that render_texture draws into, gets reset to the world layer every frame
+    current_layer: usize,
 
     target_fps: Option<u32>,
 }
@@ -59,41 +96,46 @@ impl Engine {
         env_logger::init();
         manager.start();
 
+        let window_id = self.get_window().id();
         event_loop.run(move |event, _, control_flow| {
             self.ui.platform.handle_event(&event);
 
             match event {
                 Event::WindowEvent {
                     ref event,
-                    window_id,
-                } if window_id == self.window.id() => {
+                    window_id: id,
+                } if id == window_id => {
                     if !self.input.process_events(event) {
                         self.handle_window_event(event, control_flow);
                     }
+                    if let WindowEvent::CursorMoved { position, .. } = event {
+                        self.handle_cursor_moved(vec2(position.x as f32, position.y as f32));
+                    }
                 }
                 Event::MainEventsCleared => {
                     self.time.update(&mut self.ui);
 
-                    self.update_cam();
+                    self.update_cam(self.time.get_relevant_delta_t());
                     manager.update(
                         self.time.get_relevant_delta_t(),
                         &self.input,
                         &mut self.sound,
                     );
+                    self.handle_screenshot_key();
                     self.input.reset_buttons();
 
                     match self.target_fps {
                         Some(fps) => {
                             if self.time.get_time_since_last_render() >= 0.95 / fps as f64 {
-                                self.window.request_redraw();
+                                self.get_window().request_redraw();
                             }
                         }
                         None => {
-                            self.window.request_redraw();
+                            self.get_window().request_redraw();
                         }
                     }
                 }
-                Event::RedrawRequested(window_id) if window_id == self.window.id() => {
+                Event::RedrawRequested(id) if id == window_id => {
                     self.handle_rendering(&mut manager, control_flow);
                 }
                 _ => {}
@@ -101,11 +143,129 @@ impl Engine {
         });
     }
 
+    /// Runs `manager` for `ticks` ticks without a window, updating with a fixed `delta_t` and rendering
+    /// every tick, and returns the last rendered frame. The engine has to be built with `build_headless`.
+    /// `manager.start` only runs on the first call, so a game can be stepped in several calls.
+    pub fn run_headless<T>(&mut self, manager: &mut T, ticks: u32, delta_t: f64) -> image::RgbaImage
+    where
+        T: Manager,
+    {
+        self.run_headless_with_input(manager, ticks, delta_t, |_, _| {})
+    }
+
+    /// Same as `run_headless`, but `set_input` can change the input before every tick.
+    pub(crate) fn run_headless_with_input<T, F>(
+        &mut self,
+        manager: &mut T,
+        ticks: u32,
+        delta_t: f64,
+        mut set_input: F,
+    ) -> image::RgbaImage
+    where
+        T: Manager,
+        F: FnMut(u32, &mut Input),
+    {
+        match &mut self.canvas {
+            Canvas::Headless { started, .. } if !*started => {
+                *started = true;
+                manager.start();
+            }
+            Canvas::Headless { .. } => {}
+            Canvas::Window { .. } => {
+                panic!("run_headless requires an engine built with build_headless")
+            }
+        }
+
+        for tick in 0..ticks {
+            self.ui.platform.update_time(delta_t);
+            set_input(tick, &mut self.input);
+
+            self.update_cam(delta_t);
+            manager.update(delta_t, &self.input, &mut self.sound);
+            self.input.reset_buttons();
+
+            manager.render(self);
+            self.upload_cam();
+            self.update_buffers();
+            self.render()
+                .expect("rendering into a headless texture can't fail");
+        }
+
+        self.capture_frame()
+            .expect("headless frames can always be captured")
+    }
+
+    fn get_window(&self) -> &Window {
+        match &self.canvas {
+            Canvas::Window { window, .. } => window,
+            Canvas::Headless { .. } => panic!("a headless engine has no window"),
+        }
+    }
+
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let (output, view) = match &self.canvas {
+            Canvas::Window { surface, .. } => {
+                let output = surface.get_current_texture()?;
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(output), view)
+            }
+            Canvas::Headless { texture, .. } => (
+                None,
+                texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
+        };
+
+        // Textures may have been added or removed since the last frame
+        self.textures.prepare(&self.device);
+
+        let split_textures = self.textures.get_binding() == TextureBinding::Single;
+        let draws: Vec<Vec<Draw>> = self
+            .layers
+            .iter()
+            .map(|layer| {
+                let range = self.layers[layer.source].instance_range.clone();
+                // A render target can't show itself while it's drawn into
+                let target = layer.target.as_ref().map(|target| target.get_id());
+                let drawable = |index| self.textures.is_drawable(index) && Some(index) != target;
+                vert_buffers::split_into_draws(
+                    &self.instances,
+                    &self.material_ids,
+                    range,
+                    split_textures,
+                    drawable,
+                )
+            })
+            .collect();
+        for draw in draws.iter().flatten() {
+            if let Some(material) = self.materials.get(draw.material) {
+                let layout = &self.materials.params_layout;
+                let shader = &material.shader;
+                self.textures
+                    .prepare_material(&self.device, draw.material, shader, layout);
+            }
+        }
+
+        // Render targets only get drawn into again when sprites were drawn into them this frame
+        let targets: Vec<usize> = (0..self.layers.len())
+            .filter(|&i| match &self.layers[i].target {
+                Some(target) => !target.texture.is_unloaded() && !draws[i].is_empty(),
+                None => false,
+            })
+            .collect();
+        for &i in &targets {
+            if let Some(target) = &self.layers[i].target {
+                self.textures.prepare_target(&self.device, target.get_id());
+            }
+        }
+
+        // With post effects the scene goes into a texture of its own, the last effect renders into the frame
+        let scene_size = self.get_target_size();
+        if self.post_effects.is_active() {
+            let (width, height) = (scene_size.x as u32, scene_size.y as u32);
+            self.post_effects.prepare(&self.device, width, height);
+        }
 
         let mut encoder = self
             .device
@@ -113,35 +273,53 @@ impl Engine {
                 label: Some("Render Encoder"),
             });
 
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Render Pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(self.win_background_color),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
-
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-        render_pass.set_bind_group(2, &self.win_bind_group, &[]);
-
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, self.tex_coords_buffer.slice(..));
-        render_pass.set_vertex_buffer(2, self.instance_buffer.slice(..));
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        // Render targets come first so the scene can show them, then the scene, the post effects and the UI
+        for &i in &targets {
+            let target = self.layers[i].target.as_ref();
+            if let Some(LayerTarget {
+                texture,
+                clear_color,
+            }) = target
+            {
+                self.render_layers(
+                    &mut encoder,
+                    &texture.view,
+                    *clear_color,
+                    &[i],
+                    &draws,
+                    target,
+                );
+            }
+        }
 
-        if let Some(tex_bind) = &self.tex_bind {
-            render_pass.set_bind_group(0, tex_bind, &[]);
-            render_pass.draw_indexed(0..6, 0, 0..self.instances_rendered as u32);
+        // With a virtual resolution the scene ends up in a low resolution texture that gets scaled into the frame
+        let scene_output = match &self.upscaler {
+            Some(upscaler) => upscaler.get_view(),
+            None => &view,
+        };
+        let scene_view = match self.post_effects.is_active() {
+            true => self.post_effects.get_scene_view(),
+            false => scene_output,
+        };
+        let scene_layers: Vec<usize> = (0..self.layers.len())
+            .filter(|&i| self.layers[i].target.is_none())
+            .collect();
+        let background = self.win_background_color;
+        self.render_layers(
+            &mut encoder,
+            scene_view,
+            background,
+            &scene_layers,
+            &draws,
+            None,
+        );
+        self.post_effects.apply(&mut encoder, scene_output);
+        if let Some(upscaler) = &self.upscaler {
+            let frame_size = vec2(self.config.width as f32, self.config.height as f32);
+            upscaler.apply(&mut encoder, &view, frame_size);
         }
 
+        // The UI goes on top of the effects so they don't change it
         if self.ui.should_render() {
             // Begin to draw the UI frame.
             self.ui.platform.begin_frame();
@@ -151,51 +329,166 @@ impl Engine {
                 &self.time,
                 self.target_fps,
                 self.instances_rendered,
+                &self.textures.get_indices().get_live(),
             );
             self.ui.render_game();
 
+            let window = match &self.canvas {
+                Canvas::Window { window, .. } => Some(window),
+                Canvas::Headless { .. } => None,
+            };
             let (paint_jobs, screen_descriptor) =
                 self.ui
-                    .update_egui_rpass(&self.window, &self.config, &self.device, &self.queue);
+                    .update_egui_rpass(window, &self.config, &self.device, &self.queue);
 
             self.ui
                 .egui_rpass
-                .execute_with_renderpass(&mut render_pass, &paint_jobs, &screen_descriptor)
+                .execute(&mut encoder, &view, &paint_jobs, &screen_descriptor, None)
                 .unwrap();
         }
 
-        drop(render_pass);
+        if let (Some(output), Some(frame_capture)) = (&output, &self.frame_capture) {
+            encoder.copy_texture_to_texture(
+                output.texture.as_image_copy(),
+                frame_capture.as_image_copy(),
+                frame_capture.size(),
+            );
+        }
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+        if let Some(output) = output {
+            output.present();
+        }
 
-        self.instances = Vec::with_capacity(self.instances_rendered);
-        self.tex_coords = Vec::with_capacity(self.instances_rendered);
         self.instances_rendered = 0;
+        self.current_layer = 0;
+        self.glyph_cache.clear_if_full();
         self.time.enable_prev_iter_was_render();
         Ok(())
     }
 
+    /// Draws the sprites of `layers` into `view`, which is the texture of `target` or has the size of the scene.
+    fn render_layers(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        clear_color: wgpu::Color,
+        layers: &[usize],
+        draws: &[Vec<Draw>],
+        target: Option<&LayerTarget>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear_color),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.tex_coords_buffer.slice(..));
+        render_pass.set_vertex_buffer(2, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+
+        // A viewport has to lie within the render target, nothing is visible if it lies outside of it
+        let size = match target {
+            Some(target) => target.texture.get_size(),
+            None => self.get_target_size(),
+        };
+        let full_target = rect32(0., 0., size.x, size.y);
+        for &i in layers {
+            let (layer, draws) = (&self.layers[i], &draws[i]);
+            let viewport = match layer.camera.get_viewport() {
+                Some(viewport) => viewport.intersect(full_target),
+                None => Some(full_target),
+            };
+
+            if let (Some(v), false) = (viewport, draws.is_empty()) {
+                render_pass.set_viewport(v.x, v.y, v.w, v.h, 0., 1.);
+                render_pass.set_bind_group(1, &layer.camera_bind_group, &[]);
+
+                for draw in draws {
+                    let texture = draw.texture.unwrap_or(WHITE_PIXEL_INDEX);
+                    let tex_bind = match target {
+                        Some(target) => self
+                            .textures
+                            .get_target_bind_group(texture, target.get_id()),
+                        None => self.textures.get_bind_group(texture),
+                    };
+                    let (Some(pipeline), Some(tex_bind)) =
+                        (self.textures.get_pipeline(draw.material), tex_bind)
+                    else {
+                        continue;
+                    };
+                    render_pass.set_pipeline(pipeline);
+                    render_pass.set_bind_group(0, tex_bind, &[]);
+                    if let Some(material) = self.materials.get(draw.material) {
+                        render_pass.set_bind_group(2, &material.params_bind_group, &[]);
+                    }
+                    render_pass.draw_indexed(0..6, 0, draw.range.clone());
+                }
+            }
+        }
+    }
+
     pub fn render_texture(&mut self, rect: Rect32, texture: &Texture) {
-        self.render_tex(rect, texture, 0., TexCoords::default());
+        let tex_coords = TexCoords::from_tex(texture);
+        self.render_tex(rect, texture, &DrawParams::default(), tex_coords);
     }
     pub fn render_texture_ex(&mut self, rect: Rect32, texture: &Texture, draw_params: DrawParams) {
         let tex_coords = match draw_params.source {
             Some(rect) => TexCoords::from_rect_tex(rect, texture),
-            None => TexCoords::default(),
+            None => TexCoords::from_tex(texture),
         };
-        self.render_tex(rect, texture, draw_params.rotation, tex_coords);
+        self.render_tex(rect, texture, &draw_params, tex_coords);
     }
-    fn render_tex(&mut self, rect: Rect32, tex: &Texture, rotation: f32, tex_coords: TexCoords) {
-        let inst = Instance::new(rect, rotation, tex.index);
-
-        self.instances.push(inst);
-        self.tex_coords.push(tex_coords);
+    fn render_tex(
+        &mut self,
+        rect: Rect32,
+        tex: &Texture,
+        draw_params: &DrawParams,
+        tex_coords: TexCoords,
+    ) {
+        if tex.is_unloaded() {
+            return;
+        }
+        let inst = Instance::new(rect, draw_params, tex.index, tex.sampler);
+        let material = draw_params.material.map_or(0, |material| material.id);
+        self.push_instance(inst, tex_coords, draw_params.z, material);
+    }
+    fn push_instance(&mut self, inst: Instance, tex_coords: TexCoords, z: f32, material: u32) {
+        let layer = &mut self.layers[self.current_layer];
+        layer.instances.push(inst);
+        layer.tex_coords.push(tex_coords);
+        layer.z.push(z);
+        layer.material_ids.push(material);
 
         self.instances_rendered += 1;
     }
 
     fn update_buffers(&mut self) {
-        if self.instance_buffer.size() == self.instances.len() as u64 * 28 {
+        // Put the sprites of all layers after each other, append leaves the capacity of the layers intact
+        self.instances.clear();
+        self.tex_coords.clear();
+        self.material_ids.clear();
+        for layer in &mut self.layers {
+            layer.sort_by_z();
+            layer.z.clear();
+
+            let start = self.instances.len() as u32;
+            self.instances.append(&mut layer.instances);
+            self.tex_coords.append(&mut layer.tex_coords);
+            self.material_ids.append(&mut layer.material_ids);
+            layer.instance_range = start..self.instances.len() as u32;
+        }
+
+        if self.instance_buffer.size() == (self.instances.len() * size_of::<Instance>()) as u64 {
             self.queue.write_buffer(
                 &self.instance_buffer,
                 0,
@@ -205,7 +498,8 @@ impl Engine {
             self.instance_buffer = vert_buffers::create_inst_buffer(&self.device, &self.instances);
         }
 
-        if self.tex_coords_buffer.size() == self.tex_coords.len() as u64 * 32 {
+        if self.tex_coords_buffer.size() == (self.tex_coords.len() * size_of::<TexCoords>()) as u64
+        {
             self.queue.write_buffer(
                 &self.tex_coords_buffer,
                 0,
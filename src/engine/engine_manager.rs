@@ -1,13 +1,15 @@
+use crate::camera::Camera;
+use crate::capture;
 use crate::create_Engine_from_AllFields;
-use crate::engine::Engine;
-use crate::engine_builder::{
-    create_render_pipeline, create_render_pipeline_layout, create_win_layout, AllFields,
-};
-use crate::prelude::{Manager, UserUi};
-use crate::texture::{self, Texture};
+use crate::engine::{Canvas, Engine};
+use crate::engine_builder::AllFields;
+use crate::layer::{Layer, SCREEN_LAYER};
+use crate::prelude::{Manager, UserUi, Vec32};
+use cgmath::vec2;
 use rodio::Decoder;
 use std::fs::File;
 use std::io::BufReader;
+use std::time::{SystemTime, UNIX_EPOCH};
 use winit::{
     event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::ControlFlow,
@@ -19,6 +21,7 @@ impl Engine {
         T: Manager + 'static,
     {
         manager.render(self);
+        self.upload_cam();
         self.update_buffers();
         match self.render() {
             Ok(_) => {}
@@ -31,21 +34,172 @@ impl Engine {
         }
     }
 
-    pub(crate) fn update_cam(&mut self) {
-        if self.camera.update(&self.input) {
-            self.queue.write_buffer(
-                &self.camera_buffer,
-                0,
-                bytemuck::cast_slice(&[self.camera.uniform]),
-            );
+    pub(crate) fn update_cam(&mut self, delta_t: f64) {
+        for layer in &mut self.layers {
+            layer.camera.update(&self.input, delta_t);
+        }
+        self.upload_cam();
+
+        let cursor_pos = self.input.get_cursor_pos();
+        let world_pos = self.screen_to_world(vec2(cursor_pos.x as f32, cursor_pos.y as f32));
+        self.input
+            .set_cursor_world_pos(vec2(world_pos.x as f64, world_pos.y as f64));
+    }
+
+    pub(crate) fn upload_cam(&mut self) {
+        for layer in &mut self.layers {
+            if let Some(uniform) = layer.camera.take_uniform() {
+                self.queue
+                    .write_buffer(&layer.camera_buffer, 0, bytemuck::cast_slice(&[uniform]));
+            }
+        }
+    }
+
+    /// Returns the ratio between physical pixels and logical pixels of the window, 1 when headless.
+    pub fn get_scale_factor(&self) -> f64 {
+        match &self.canvas {
+            Canvas::Window { window, .. } => window.scale_factor(),
+            Canvas::Headless { .. } => 1.,
+        }
+    }
+
+    /// Converts physical pixels of the window, like `Input::get_cursor_pos`, to world coordinates.
+    /// With a virtual resolution the pixels are the ones of the virtual resolution.
+    pub fn screen_to_world(&self, screen_pos: Vec32) -> Vec32 {
+        self.get_camera()
+            .screen_to_world(screen_pos, self.get_target_size())
+    }
+    /// Converts world coordinates to physical pixels of the window, or of the virtual resolution if there is one.
+    pub fn world_to_screen(&self, world_pos: Vec32) -> Vec32 {
+        self.get_camera()
+            .world_to_screen(world_pos, self.get_target_size())
+    }
+    /// Converts screen pixels, the ones of the virtual resolution if there is one and physical pixels of the
+    /// window otherwise, to logical pixels of the window, which don't depend on the DPI.
+    pub fn screen_to_logical(&self, screen_pos: Vec32) -> Vec32 {
+        let window_pos = match &self.upscaler {
+            Some(upscaler) => upscaler.virtual_to_frame(screen_pos, self.get_frame_size()),
+            None => screen_pos,
+        };
+        window_pos / self.get_scale_factor() as f32
+    }
+    /// Converts logical pixels of the window to screen pixels, the inverse of `screen_to_logical`.
+    pub fn logical_to_screen(&self, logical_pos: Vec32) -> Vec32 {
+        let window_pos = logical_pos * self.get_scale_factor() as f32;
+        match &self.upscaler {
+            Some(upscaler) => upscaler.frame_to_virtual(window_pos, self.get_frame_size()),
+            None => window_pos,
+        }
+    }
+
+    /// The size of the scene in pixels, the virtual resolution if there is one.
+    pub(crate) fn get_target_size(&self) -> Vec32 {
+        match &self.upscaler {
+            Some(upscaler) => upscaler.get_size(),
+            None => self.get_frame_size(),
         }
     }
 
+    // The size of the window or the headless texture in physical pixels
+    fn get_frame_size(&self) -> Vec32 {
+        vec2(self.config.width as f32, self.config.height as f32)
+    }
+
+    /// With a virtual resolution the cursor position is in pixels of the virtual resolution.
+    pub(crate) fn handle_cursor_moved(&mut self, window_pos: Vec32) {
+        if let Some(upscaler) = &self.upscaler {
+            let pos = upscaler.frame_to_virtual(window_pos, self.get_frame_size());
+            self.input.set_cursor_pos(vec2(pos.x as f64, pos.y as f64));
+        }
+    }
+
+    /// Returns the camera of the world layer.
+    pub fn get_camera(&self) -> &Camera {
+        &self.layers[0].camera
+    }
+    pub fn get_camera_mut(&mut self) -> &mut Camera {
+        &mut self.layers[0].camera
+    }
+
+    /// Adds a layer with its own sprites and camera, drawn on top of the existing layers but below the screen layer.
+    pub fn add_layer(&mut self, name: &str) -> Result<(), String> {
+        let index = self.insert_layer(name)?;
+        self.layers[index].source = index;
+        Ok(())
+    }
+
+    /// Adds a layer that draws the sprites of `source` with its own camera, useful for split-screen and minimaps.
+    pub fn add_layer_view(&mut self, name: &str, source: &str) -> Result<(), String> {
+        let source = self.get_layer_index(source)?;
+        let index = self.insert_layer(name)?;
+        // Inserting shifts the screen layer
+        self.layers[index].source = if source >= index { source + 1 } else { source };
+        Ok(())
+    }
+
+    fn insert_layer(&mut self, name: &str) -> Result<usize, String> {
+        if self.get_layer_index(name).is_ok() {
+            return Err(format!("layer {name} already exists"));
+        }
+
+        let camera = Camera::new(self.get_camera().get_size());
+        let layer = Layer::new(&self.device, name, camera, 0);
+        // Layers of render targets come after the screen layer
+        let index = self.get_layer_index(SCREEN_LAYER)?;
+        self.layers.insert(index, layer);
+
+        // Views before the insertion point can show layers after it too
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            if i != index && layer.source >= index {
+                layer.source += 1;
+            }
+        }
+        Ok(index)
+    }
+
+    fn get_layer_index(&self, name: &str) -> Result<usize, String> {
+        // The layers of render targets are only found through their RenderTarget
+        let found = |layer: &Layer| layer.target.is_none() && layer.name == name;
+        match self.layers.iter().position(found) {
+            Some(index) => Ok(index),
+            None => Err(format!("layer {name} does not exist")),
+        }
+    }
+
+    /// Makes the following render calls of this frame draw into the layer `name`.
+    /// Every frame starts out drawing into the world layer.
+    pub fn set_render_layer(&mut self, name: &str) -> Result<(), String> {
+        self.current_layer = self.get_layer_index(name)?;
+        Ok(())
+    }
+
+    pub fn get_layer_camera(&self, name: &str) -> Option<&Camera> {
+        let index = self.get_layer_index(name).ok()?;
+        Some(&self.layers[index].camera)
+    }
+    pub fn get_layer_camera_mut(&mut self, name: &str) -> Option<&mut Camera> {
+        let index = self.get_layer_index(name).ok()?;
+        Some(&mut self.layers[index].camera)
+    }
+
     pub(crate) fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         self.win_size = new_size;
         self.config.width = new_size.width;
         self.config.height = new_size.height;
-        self.surface.configure(&self.device, &self.config);
+        match &mut self.canvas {
+            Canvas::Window { surface, .. } => surface.configure(&self.device, &self.config),
+            Canvas::Headless { texture, .. } => {
+                *texture =
+                    capture::create_frame_texture(&self.device, &self.config, "Headless Texture")
+            }
+        }
+        if self.frame_capture.is_some() {
+            self.frame_capture = Some(capture::create_frame_texture(
+                &self.device,
+                &self.config,
+                "Frame Capture Texture",
+            ));
+        }
     }
 
     pub(crate) fn handle_window_event(
@@ -75,10 +229,43 @@ impl Engine {
     }
 
     pub(crate) fn new(all_fields: AllFields) -> Engine {
-        create_Engine_from_AllFields!(all_fields, input window win_bind_group win_size win_background_color
-        surface device queue config render_pipeline vertex_buffer index_buffer camera camera_bind_group
-        camera_buffer instance_buffer instances instances_rendered time tex_bind
-        texture_amt_created target_fps sound ui tex_coords_buffer tex_coords use_near_filter_mode)
+        create_Engine_from_AllFields!(all_fields, input canvas win_size win_background_color
+        device queue config vertex_buffer index_buffer layers current_layer
+        instance_buffer instances instances_rendered time textures target_fps sound ui tex_coords_buffer tex_coords material_ids use_near_filter_mode
+        frame_capture screenshot_key glyph_cache materials post_effects upscaler)
+    }
+
+    pub(crate) fn handle_screenshot_key(&self) {
+        match self.screenshot_key {
+            Some(key) if self.input.is_button_pressed(key) => {
+                let millis = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_millis());
+                if let Err(e) = self.save_screenshot(&format!("screenshot_{millis}.png")) {
+                    log::error!("failed to save screenshot: {e}");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the last rendered frame, or None if frame capture isn't enabled for this window.
+    pub fn capture_frame(&self) -> Option<image::RgbaImage> {
+        let texture = match (&self.canvas, &self.frame_capture) {
+            (Canvas::Headless { texture, .. }, _) => texture,
+            (Canvas::Window { .. }, Some(frame_capture)) => frame_capture,
+            (Canvas::Window { .. }, None) => return None,
+        };
+        Some(capture::read_texture(&self.device, &self.queue, texture))
+    }
+
+    /// Saves the last rendered frame to `path`, the image format is derived from the extension.
+    pub fn save_screenshot(&self, path: &str) -> Result<(), String> {
+        let frame = match self.capture_frame() {
+            Some(frame) => frame,
+            None => return Err("frame capture is not enabled".to_string()),
+        };
+        frame.save(path).map_err(|e| e.to_string())
     }
 
     pub fn create_sound_source(&self, path: &str) -> Result<Decoder<BufReader<File>>, String> {
@@ -108,68 +295,6 @@ impl Engine {
         self.sound.play_sound(source)
     }
 
-    fn generic_use_textures(&mut self, tex_layout: wgpu::BindGroupLayout) {
-        let cam_layout = crate::camera::create_bind_group_layout(&self.device);
-        let win_layout = create_win_layout(&self.device);
-        let pipeline_layout =
-            create_render_pipeline_layout(&self.device, &tex_layout, &cam_layout, &win_layout);
-
-        let shader = crate::engine_builder::create_shader(&self.device);
-        self.render_pipeline =
-            create_render_pipeline(&self.device, &pipeline_layout, &shader, &self.config);
-    }
-
-    pub fn use_textures(&mut self, textures: &Vec<Texture>) {
-        let tex_layout = texture::create_bind_group_layout(&self.device, textures.len() as u32);
-        self.tex_bind = Some(texture::create_bind_group(
-            &self.device,
-            &tex_layout,
-            textures,
-        ));
-
-        self.generic_use_textures(tex_layout);
-    }
-
-    pub fn create_and_use_texture(&mut self, bytes: &[u8]) -> Result<Texture, &'static str> {
-        let tex = match Texture::from_bytes(
-            &self.device,
-            &self.queue,
-            self.texture_amt_created,
-            bytes,
-            self.use_near_filter_mode,
-        ) {
-            Ok(tex) => tex,
-            Err(_) => return Err("failed to create texture"),
-        };
-        self.texture_amt_created += 1;
-
-        let tex_layout = texture::create_bind_group_layout(&self.device, 1);
-        self.tex_bind = Some(texture::create_bind_group_single_tex(
-            &self.device,
-            &tex_layout,
-            &tex,
-        ));
-        self.generic_use_textures(tex_layout);
-
-        Ok(tex)
-    }
-
-    pub fn create_texture(&mut self, bytes: &[u8]) -> Result<Texture, &'static str> {
-        let tex = match Texture::from_bytes(
-            &self.device,
-            &self.queue,
-            self.texture_amt_created,
-            bytes,
-            self.use_near_filter_mode,
-        ) {
-            Ok(tex) => tex,
-            Err(_) => return Err("failed to create texture"),
-        };
-
-        self.texture_amt_created += 1;
-        Ok(tex)
-    }
-
     pub fn get_avg_tps(&self) -> u32 {
         self.time.get_avg_tps()
     }
@@ -0,0 +1,50 @@
+use crate::engine::Engine;
+use crate::engine_builder::get_shader_source;
+use crate::material::{Material, Materials};
+
+impl Engine {
+    /// Creates a material from WGSL code that defines
+    /// `fn material(in: VertexOutput, tex_color: vec4<f32>) -> vec4<f32>`, which returns the color of a pixel.
+    /// `in` has the fields `tex_coords`, `index` and `sampler_index` (of the texture), `color` (of `DrawParams`)
+    /// and `kind`, `tex_color` is the texture sampled at `in.tex_coords`. The code can sample the texture
+    /// elsewhere with `sample_texture(in.index, in.sampler_index, coords)`, as long as it doesn't happen in a
+    /// branch, and read the parameters set with `set_material_params` with `get_param(i)`.
+    pub fn create_material(&mut self, fragment: &str) -> Result<Material, String> {
+        let binding = self.textures.get_binding();
+        let source = Materials::compose_source(&get_shader_source(binding), fragment);
+
+        // Invalid code would panic otherwise
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Material Shader"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+        let material = self.materials.add(&self.device, shader);
+        if let Some(data) = self.materials.get(material.id) {
+            let layout = &self.materials.params_layout;
+            self.textures
+                .prepare_material(&self.device, material.id, &data.shader, layout);
+        }
+
+        match pollster::block_on(self.device.pop_error_scope()) {
+            None => Ok(material),
+            Some(e) => {
+                self.textures.remove_material(material.id);
+                self.materials.remove_last();
+                Err(format!("could not create material: {e}"))
+            }
+        }
+    }
+
+    /// Sets the values `get_param` returns in the code of `material`, at most `MATERIAL_PARAM_AMT` of them.
+    /// Parameters start out as 0, the ones after `params` keep their values.
+    pub fn set_material_params(
+        &mut self,
+        material: Material,
+        params: &[f32],
+    ) -> Result<(), String> {
+        self.materials.set_params(&self.queue, material, params)
+    }
+}
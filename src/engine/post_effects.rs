@@ -0,0 +1,98 @@
+use crate::builtin_effect::BuiltinEffect;
+use crate::engine::Engine;
+use crate::post_effect::PostEffect;
+
+impl Engine {
+    /// Creates a post effect from WGSL code that defines `fn effect(uv: vec2<f32>, color: vec4<f32>) -> vec4<f32>`,
+    /// which returns the color of a pixel of the screen. `uv` goes from (0, 0) at the top left to (1, 1) at the
+    /// bottom right and `color` is the output of the previous pass at `uv`. The code can sample that output
+    /// elsewhere with `sample_scene(uv)`, get its size in pixels with `get_scene_size()` and read the parameters
+    /// set with `set_post_effect_params` with `get_param(i)`. Depending on the format of the window the colors
+    /// are linear or as stored in images, `scene_to_linear` and `linear_to_scene` convert them either way.
+    /// The effect does nothing until it's added.
+    pub fn create_post_effect(&mut self, fragment: &str) -> Result<PostEffect, String> {
+        let source = self.post_effects.compose_source(fragment);
+
+        // Invalid code would panic otherwise
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Post Effect Shader"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+        let effect = self.post_effects.add(&self.device, &shader, None);
+
+        match pollster::block_on(self.device.pop_error_scope()) {
+            None => Ok(effect),
+            Some(e) => {
+                self.post_effects.remove_last();
+                Err(format!("could not create post effect: {e}"))
+            }
+        }
+    }
+
+    /// Creates one of the effects that come with the engine, it does nothing until it's added.
+    pub fn create_builtin_effect(&mut self, effect: BuiltinEffect) -> PostEffect {
+        let source = self.post_effects.compose_source(effect.get_source());
+        let shader = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Builtin Effect Shader"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+        let handle = self.post_effects.add(&self.device, &shader, Some(&effect));
+        self.set_builtin_effect_params(handle, effect)
+            .expect("the effect was just created with these parameters");
+        handle
+    }
+
+    /// Changes the parameters of a built-in effect, `params` has to be the same kind of effect it was created as.
+    pub fn set_builtin_effect_params(
+        &mut self,
+        effect: PostEffect,
+        params: BuiltinEffect,
+    ) -> Result<(), String> {
+        self.post_effects
+            .set_builtin_params(&self.device, &self.queue, effect, &params)
+    }
+
+    /// Runs `effect` after the effects already in use, if it isn't in use yet. The UI isn't affected by effects.
+    pub fn add_post_effect(&mut self, effect: PostEffect) {
+        if !self.post_effects.get_chain().contains(&effect) {
+            let mut chain = self.post_effects.get_chain().to_vec();
+            chain.push(effect);
+            self.post_effects.set_chain(&chain);
+        }
+    }
+    pub fn remove_post_effect(&mut self, effect: PostEffect) {
+        let mut chain = self.post_effects.get_chain().to_vec();
+        chain.retain(|e| *e != effect);
+        self.post_effects.set_chain(&chain);
+    }
+    /// Adds or removes `effect`, for effects that get switched on and off in the settings of a game.
+    pub fn set_post_effect_enabled(&mut self, effect: PostEffect, enabled: bool) {
+        match enabled {
+            true => self.add_post_effect(effect),
+            false => self.remove_post_effect(effect),
+        }
+    }
+    /// Replaces the effects in use, they run in the order of `effects`.
+    pub fn set_post_effects(&mut self, effects: &[PostEffect]) {
+        self.post_effects.set_chain(effects);
+    }
+    /// The effects in use, in the order they run in.
+    pub fn get_post_effects(&self) -> &[PostEffect] {
+        self.post_effects.get_chain()
+    }
+
+    /// Sets the values `get_param` returns in the code of `effect`, at most `POST_EFFECT_PARAM_AMT` of them.
+    /// Parameters start out as 0, the ones after `params` keep their values.
+    pub fn set_post_effect_params(
+        &mut self,
+        effect: PostEffect,
+        params: &[f32],
+    ) -> Result<(), String> {
+        self.post_effects.set_params(&self.queue, effect, params)
+    }
+}
@@ -0,0 +1,133 @@
+use crate::engine::Engine;
+use crate::layer::Layer;
+use crate::math::Vec32;
+use crate::minor_types::Color;
+use crate::prelude::Camera;
+use crate::render_target::{LayerTarget, RenderTarget};
+use crate::texture::{Texture, TextureError, TextureParams};
+
+impl Engine {
+    /// Creates a transparent texture of `size` pixels that sprites can be drawn into, see `set_render_target`.
+    /// It's drawable like any other texture right away, for minimaps, cached backgrounds or reflections.
+    pub fn create_render_target(&mut self, size: Vec32) -> Result<RenderTarget, TextureError> {
+        self.create_render_target_with_params(size, self.get_default_texture_params())
+    }
+
+    /// Like `create_render_target`, but with its own filter and address mode for drawing the target.
+    pub fn create_render_target_with_params(
+        &mut self,
+        size: Vec32,
+        params: TextureParams,
+    ) -> Result<RenderTarget, TextureError> {
+        let (width, height) = (size.x.round() as u32, size.y.round() as u32);
+        let max = self.device.limits().max_texture_dimension_2d;
+        if width == 0 || height == 0 || width > max || height > max {
+            return Err(TextureError::Dimensions { width, height, max });
+        }
+
+        let params = TextureParams {
+            mipmaps: false,
+            ..params
+        };
+        let texture = Texture::render_target(
+            &self.device,
+            width,
+            height,
+            self.config.format,
+            self.textures.get_indices().next(),
+            params,
+        );
+        self.add_texture(&texture);
+
+        // The camera shows the whole target, with one world unit per pixel
+        let camera = Camera::new(texture.get_size());
+        let name = format!("render target {}", texture.index);
+        let mut layer = Layer::new(&self.device, &name, camera, self.layers.len());
+        layer.target = Some(LayerTarget {
+            texture: texture.clone(),
+            clear_color: wgpu::Color::TRANSPARENT,
+        });
+        self.layers.push(layer);
+        Ok(RenderTarget::new(texture))
+    }
+
+    /// Makes the following render calls of this frame draw into `target` with the camera of the target, until
+    /// `set_render_layer` picks a layer again. Every frame starts out drawing into the world layer.
+    /// Sprites that show the target itself get skipped while drawing into it.
+    pub fn set_render_target(&mut self, target: &RenderTarget) -> Result<(), String> {
+        self.current_layer = self.get_target_layer_index(target)?;
+        Ok(())
+    }
+
+    /// Stops drawing into `target`, its texture keeps the pixels drawn last and stays drawable.
+    pub fn remove_render_target(&mut self, target: &RenderTarget) {
+        let Ok(index) = self.get_target_layer_index(target) else {
+            return;
+        };
+        self.layers.remove(index);
+        for layer in &mut self.layers[index..] {
+            layer.source -= 1;
+        }
+        // Drawing into a later target keeps going into it, it just moved down
+        if self.current_layer == index {
+            self.current_layer = 0;
+        } else if self.current_layer > index {
+            self.current_layer -= 1;
+        }
+    }
+
+    /// Sets the color the target gets cleared to before sprites are drawn into it, transparent by default.
+    pub fn set_render_target_background(
+        &mut self,
+        target: &RenderTarget,
+        color: Color,
+    ) -> Result<(), String> {
+        let index = self.get_target_layer_index(target)?;
+        if let Some(layer_target) = &mut self.layers[index].target {
+            layer_target.clear_color = color.to_wgpu();
+        }
+        Ok(())
+    }
+
+    pub fn get_render_target_camera(&self, target: &RenderTarget) -> Option<&Camera> {
+        let index = self.get_target_layer_index(target).ok()?;
+        Some(&self.layers[index].camera)
+    }
+    pub fn get_render_target_camera_mut(&mut self, target: &RenderTarget) -> Option<&mut Camera> {
+        let index = self.get_target_layer_index(target).ok()?;
+        Some(&mut self.layers[index].camera)
+    }
+
+    fn get_target_layer_index(&self, target: &RenderTarget) -> Result<usize, String> {
+        let is_target = |layer: &Layer| match &layer.target {
+            Some(layer_target) => layer_target.get_id() == target.get_id(),
+            None => false,
+        };
+        match self.layers.iter().position(is_target) {
+            Some(index) => Ok(index),
+            None => Err("the render target was removed".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::{block_on, vec2, EngineBuilder};
+
+    #[test]
+    fn removing_a_target_keeps_drawing_into_a_later_one() {
+        let mut engine = block_on(EngineBuilder::new(vec2(64., 64.)).build_headless());
+        let first = engine.create_render_target(vec2(16., 16.)).unwrap();
+        let second = engine.create_render_target(vec2(16., 16.)).unwrap();
+
+        engine.set_render_target(&second).unwrap();
+        engine.remove_render_target(&first);
+        assert_eq!(
+            Ok(engine.current_layer),
+            engine.get_target_layer_index(&second)
+        );
+
+        engine.remove_render_target(&second);
+        assert_eq!(engine.current_layer, 0);
+    }
+}
@@ -0,0 +1,155 @@
+use std::f32::consts::TAU;
+
+use cgmath::{vec2, InnerSpace};
+
+use crate::engine::Engine;
+use crate::math::{rect32, Rect32, Vec32};
+use crate::minor_types::ShapeParams;
+use crate::vert_buffers::{Instance, InstanceKind, TexCoords};
+
+impl Engine {
+    pub fn draw_rect(&mut self, rect: Rect32, params: impl Into<ShapeParams>) {
+        let params = params.into();
+        let (x_axis, y_axis) = (vec2(rect.w, 0.), vec2(0., rect.h));
+        self.draw_shape(
+            vec2(rect.x, rect.y),
+            x_axis,
+            y_axis,
+            params,
+            InstanceKind::Rect,
+        );
+    }
+    /// Draws the border of `rect`, the border lies inside of the rect.
+    pub fn draw_rect_outline(
+        &mut self,
+        rect: Rect32,
+        thickness: f32,
+        params: impl Into<ShapeParams>,
+    ) {
+        let params = params.into();
+        let t = thickness.min(rect.w * 0.5).min(rect.h * 0.5);
+        let inner_h = rect.h - 2. * t;
+
+        self.draw_rect(rect32(rect.x, rect.y, rect.w, t), params);
+        self.draw_rect(rect32(rect.x, rect.y + rect.h - t, rect.w, t), params);
+        self.draw_rect(rect32(rect.x, rect.y + t, t, inner_h), params);
+        self.draw_rect(rect32(rect.x + rect.w - t, rect.y + t, t, inner_h), params);
+    }
+
+    pub fn draw_circle(&mut self, center: Vec32, radius: f32, params: impl Into<ShapeParams>) {
+        let (x_axis, y_axis) = (vec2(2. * radius, 0.), vec2(0., 2. * radius));
+        let origin = center - vec2(radius, radius);
+        self.draw_shape(origin, x_axis, y_axis, params.into(), InstanceKind::Ellipse);
+    }
+    /// Draws the border of a circle, the border is centered on `radius`.
+    pub fn draw_circle_outline(
+        &mut self,
+        center: Vec32,
+        radius: f32,
+        thickness: f32,
+        params: impl Into<ShapeParams>,
+    ) {
+        let segments = ((radius.abs() * 0.5).ceil() as usize).clamp(16, 256);
+        let points: Vec<Vec32> = (0..segments)
+            .map(|i| {
+                let angle = i as f32 / segments as f32 * TAU;
+                center + vec2(angle.cos(), angle.sin()) * radius
+            })
+            .collect();
+        self.draw_polygon_outline(&points, thickness, params);
+    }
+
+    pub fn draw_line(
+        &mut self,
+        from: Vec32,
+        to: Vec32,
+        thickness: f32,
+        params: impl Into<ShapeParams>,
+    ) {
+        let dir = to - from;
+        if dir.magnitude2() == 0. {
+            return;
+        }
+        let normal = vec2(-dir.y, dir.x).normalize() * thickness;
+        self.draw_shape(
+            from - normal * 0.5,
+            dir,
+            normal,
+            params.into(),
+            InstanceKind::Rect,
+        );
+    }
+
+    pub fn draw_triangle(&mut self, a: Vec32, b: Vec32, c: Vec32, params: impl Into<ShapeParams>) {
+        self.draw_shape(a, b - a, c - a, params.into(), InstanceKind::Triangle);
+    }
+
+    /// Draws a filled convex polygon, the points can be in clockwise or counterclockwise order.
+    pub fn draw_polygon(&mut self, points: &[Vec32], params: impl Into<ShapeParams>) {
+        let params = params.into();
+        for i in 1..points.len().saturating_sub(1) {
+            self.draw_triangle(points[0], points[i], points[i + 1], params);
+        }
+    }
+    /// Draws the border of a closed polygon, the border is centered on the edges.
+    pub fn draw_polygon_outline(
+        &mut self,
+        points: &[Vec32],
+        thickness: f32,
+        params: impl Into<ShapeParams>,
+    ) {
+        let params = params.into();
+        let mut points = points.to_vec();
+        points.dedup();
+        if points.len() > 1 && points[0] == points[points.len() - 1] {
+            points.pop();
+        }
+        if points.len() < 3 {
+            if let [from, to] = points[..] {
+                self.draw_line(from, to, thickness, params);
+            }
+            return;
+        }
+
+        let len = points.len();
+        let normal = |a: Vec32, b: Vec32| {
+            let dir = (b - a).normalize();
+            vec2(-dir.y, dir.x)
+        };
+        // Miter the corners, so the edges join without gaps or overlap
+        let offsets: Vec<Vec32> = (0..len)
+            .map(|i| {
+                let prev = normal(points[(i + len - 1) % len], points[i]);
+                let next = normal(points[i], points[(i + 1) % len]);
+                if (prev + next).magnitude2() < 0.0001 {
+                    // The polygon turns back on itself here
+                    return next * thickness * 0.5;
+                }
+                let miter = (prev + next).normalize();
+                // Very sharp corners would get endlessly long miters
+                let cos = miter.dot(next).max(0.25);
+                miter * thickness * 0.5 / cos
+            })
+            .collect();
+
+        for i in 0..len {
+            let j = (i + 1) % len;
+            let (outer_i, inner_i) = (points[i] + offsets[i], points[i] - offsets[i]);
+            let (outer_j, inner_j) = (points[j] + offsets[j], points[j] - offsets[j]);
+            self.draw_triangle(outer_i, outer_j, inner_j, params);
+            self.draw_triangle(outer_i, inner_j, inner_i, params);
+        }
+    }
+
+    fn draw_shape(
+        &mut self,
+        origin: Vec32,
+        x_axis: Vec32,
+        y_axis: Vec32,
+        params: ShapeParams,
+        kind: InstanceKind,
+    ) {
+        let inst = Instance::new_shape(origin, x_axis, y_axis, params.color, kind);
+        self.push_instance(inst, TexCoords::default(), params.z, 0);
+    }
+}
@@ -0,0 +1,42 @@
+use crate::engine::Engine;
+use crate::math::Vec32;
+use crate::minor_types::DrawParams;
+use crate::text::{Font, TextParams};
+use crate::texture::Texture;
+use crate::vert_buffers::Instance;
+
+impl Engine {
+    /// Loads a TrueType or OpenType font.
+    pub fn create_font(&mut self, bytes: &[u8]) -> Result<Font, String> {
+        Font::from_bytes(bytes, self.glyph_cache.next_font_id())
+    }
+    /// Creates a monospaced font from a texture with glyphs of `glyph_size` pixels in a grid,
+    /// `chars` are the characters of the glyphs from left to right and top to bottom.
+    pub fn create_bitmap_font(&self, texture: &Texture, glyph_size: Vec32, chars: &str) -> Font {
+        Font::from_bitmap(texture, glyph_size, chars)
+    }
+
+    /// Draws `text` with its top left at `pos`, or top center / top right depending on the alignment.
+    pub fn draw_text(&mut self, text: &str, pos: Vec32, font: &Font, params: TextParams) {
+        if font.get_texture().is_some_and(Texture::is_unloaded) {
+            return;
+        }
+        let quads = font.layout(text, pos, &params, &mut self.glyph_cache, &self.queue);
+
+        let draw_params = DrawParams {
+            color: params.color,
+            ..Default::default()
+        };
+        let texture = font.get_texture().unwrap_or(self.glyph_cache.get_texture());
+        let (index, sampler) = (texture.index, texture.sampler);
+        for quad in quads {
+            let inst = Instance::new(quad.rect, &draw_params, index, sampler);
+            self.push_instance(inst, quad.tex_coords, params.z, 0);
+        }
+    }
+
+    /// Returns the size `text` takes up when drawn with `draw_text`.
+    pub fn measure_text(&self, text: &str, font: &Font, params: TextParams) -> Vec32 {
+        font.measure(text, &params)
+    }
+}
@@ -0,0 +1,215 @@
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use std::path::Path;
+
+use crate::atlas;
+use crate::dynamic_texture::DynamicTexture;
+use crate::engine::Engine;
+use crate::texture::{FilterMode, Texture, TextureError, TextureParams};
+use crate::texture_registry::TextureInfo;
+
+impl Engine {
+    /// Makes the textures drawable, textures that are already in use stay in use.
+    /// Textures of an atlas share their GPU texture, so adding one of them adds all of them.
+    pub fn use_textures(&mut self, textures: &[Texture]) {
+        for tex in textures {
+            self.add_texture(tex);
+        }
+    }
+    /// Makes `tex` drawable from the next frame on. Can be called at any time, even while rendering.
+    pub fn add_texture(&mut self, tex: &Texture) {
+        self.textures.add(tex);
+    }
+    /// Stops `tex` from being drawn, sprites using it get skipped. For textures of an atlas
+    /// this removes all textures of the atlas.
+    pub fn remove_texture(&mut self, tex: &Texture) {
+        self.textures.remove(tex.index);
+    }
+
+    /// Frees the GPU memory of `tex` right away instead of when the last clone of it is dropped. The texture and
+    /// its clones can't be drawn anymore, for textures of an atlas this unloads all textures of the atlas.
+    pub fn unload_texture(&mut self, tex: &Texture) {
+        if !tex.is_unloaded() {
+            self.textures.remove(tex.index);
+            tex.unload(&self.queue);
+        }
+    }
+
+    /// Returns the textures currently on the GPU, including the ones of the engine itself.
+    pub fn get_live_textures(&self) -> Vec<TextureInfo> {
+        self.textures.get_indices().get_live()
+    }
+
+    pub fn create_and_use_texture(&mut self, bytes: &[u8]) -> Result<Texture, TextureError> {
+        let tex = self.create_texture(bytes)?;
+        self.add_texture(&tex);
+        Ok(tex)
+    }
+
+    /// Creates a texture from an encoded image, like the bytes of a png file.
+    pub fn create_texture(&mut self, bytes: &[u8]) -> Result<Texture, TextureError> {
+        self.create_texture_with_params(bytes, self.get_default_texture_params())
+    }
+
+    /// Like `create_texture`, but with its own filter and address mode instead of the defaults of the engine.
+    pub fn create_texture_with_params(
+        &mut self,
+        bytes: &[u8],
+        params: TextureParams,
+    ) -> Result<Texture, TextureError> {
+        let img = image::load_from_memory(bytes)?;
+        self.create_labeled_texture(&img, None, params)
+    }
+
+    /// Loads the image file at `path`, the image format is derived from the extension.
+    pub fn create_texture_from_path(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<Texture, TextureError> {
+        let path = path.as_ref();
+        let img = image::open(path)?;
+        let label = path.to_string_lossy();
+        self.create_labeled_texture(&img, Some(&label), self.get_default_texture_params())
+    }
+
+    /// Creates a texture from `pixels`, which holds 4 bytes of RGBA for every pixel row by row.
+    pub fn create_texture_from_rgba(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<Texture, TextureError> {
+        let img = match RgbaImage::from_raw(width, height, pixels.to_vec()) {
+            // from_raw accepts more data than needed
+            Some(img) if pixels.len() as u64 == 4 * width as u64 * height as u64 => img,
+            _ => {
+                return Err(TextureError::DataSize {
+                    width,
+                    height,
+                    len: pixels.len(),
+                })
+            }
+        };
+        let img = DynamicImage::ImageRgba8(img);
+        self.create_labeled_texture(&img, None, self.get_default_texture_params())
+    }
+
+    pub fn create_texture_from_image(
+        &mut self,
+        img: &DynamicImage,
+    ) -> Result<Texture, TextureError> {
+        self.create_texture_from_image_with_params(img, self.get_default_texture_params())
+    }
+
+    /// Like `create_texture_from_image`, but with its own filter and address mode.
+    pub fn create_texture_from_image_with_params(
+        &mut self,
+        img: &DynamicImage,
+        params: TextureParams,
+    ) -> Result<Texture, TextureError> {
+        self.create_labeled_texture(img, None, params)
+    }
+
+    fn create_labeled_texture(
+        &mut self,
+        img: &DynamicImage,
+        label: Option<&str>,
+        params: TextureParams,
+    ) -> Result<Texture, TextureError> {
+        let (width, height) = img.dimensions();
+        let max = self.device.limits().max_texture_dimension_2d;
+        if width == 0 || height == 0 || width > max || height > max {
+            return Err(TextureError::Dimensions { width, height, max });
+        }
+
+        Ok(Texture::from_image(
+            &self.device,
+            &self.queue,
+            img,
+            self.textures.get_indices().next(),
+            label,
+            params,
+        ))
+    }
+
+    /// Creates a transparent texture whose pixels can be changed every frame, for pixel canvases.
+    pub fn create_dynamic_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+    ) -> Result<DynamicTexture, TextureError> {
+        self.create_dynamic_texture_with_params(width, height, self.get_default_texture_params())
+    }
+
+    /// Like `create_dynamic_texture`, but with its own filter and address mode.
+    pub fn create_dynamic_texture_with_params(
+        &mut self,
+        width: u32,
+        height: u32,
+        params: TextureParams,
+    ) -> Result<DynamicTexture, TextureError> {
+        let pixels = RgbaImage::new(width, height);
+        let img = DynamicImage::ImageRgba8(pixels.clone());
+        // uploads only change the full size version
+        let params = TextureParams {
+            mipmaps: false,
+            ..params
+        };
+        let texture = self.create_labeled_texture(&img, Some("Dynamic Texture"), params)?;
+        Ok(DynamicTexture::new(texture, pixels))
+    }
+
+    /// Uploads the pixels of `tex` that changed since the last upload, call it before drawing the texture.
+    pub fn upload_dynamic_texture(&mut self, tex: &mut DynamicTexture) {
+        tex.upload(&self.queue);
+    }
+
+    /// Packs the images into as few GPU textures as possible and returns a texture for every image, in the same
+    /// order. They can be used like any other texture, but have to be passed to `use_textures` as well.
+    pub fn create_texture_atlas(&mut self, images: &[&[u8]]) -> Result<Vec<Texture>, TextureError> {
+        self.create_texture_atlas_with_params(images, self.get_default_texture_params())
+    }
+
+    /// Like `create_texture_atlas`, but with its own filter and address mode for all textures of the atlas.
+    /// Atlases never have mipmaps, the smaller versions would blend neighbouring images of the atlas together.
+    pub fn create_texture_atlas_with_params(
+        &mut self,
+        images: &[&[u8]],
+        params: TextureParams,
+    ) -> Result<Vec<Texture>, TextureError> {
+        // The padding between the images only keeps them apart at full size
+        let params = TextureParams {
+            mipmaps: false,
+            ..params
+        };
+        let images = atlas::decode(images)?;
+        let max_size = self.device.limits().max_texture_dimension_2d.min(4096);
+        let (pages, placements) = atlas::pack(&images, max_size)?;
+
+        let pages: Vec<Texture> = pages
+            .into_iter()
+            .map(|page| {
+                Texture::from_image(
+                    &self.device,
+                    &self.queue,
+                    &DynamicImage::ImageRgba8(page),
+                    self.textures.get_indices().next(),
+                    Some("Atlas Texture"),
+                    params,
+                )
+            })
+            .collect();
+
+        Ok(placements
+            .iter()
+            .map(|placement| pages[placement.page].with_region(placement.region))
+            .collect())
+    }
+
+    /// Textures use nearest filtering if `EngineBuilder::use_near_filter_mode` was set, linear otherwise.
+    pub fn get_default_texture_params(&self) -> TextureParams {
+        match self.use_near_filter_mode {
+            true => TextureParams::from_filter_mode(FilterMode::Nearest),
+            false => TextureParams::default(),
+        }
+    }
+}
@@ -1,16 +1,22 @@
 use egui_winit_platform::{Platform, PlatformDescriptor};
-use wgpu::util::DeviceExt;
 use winit::dpi::PhysicalSize;
 use winit::event_loop::EventLoop;
 use winit::window::WindowBuilder;
 
-use crate::camera::{self, Camera};
-use crate::engine::Engine;
-use crate::minor_types::WindowUniform;
-use crate::prelude::{Sound, Vec32};
-use crate::texture::{self};
+use crate::builtin_effect::BuiltinEffect;
+use crate::camera::Camera;
+use crate::capture;
+use crate::engine::{Canvas, Engine};
+use crate::layer::{Layer, SCREEN_LAYER, WORLD_LAYER};
+use crate::material::Materials;
+use crate::post_effect::PostEffects;
+use crate::prelude::{vec2, Button, Sound, Vec32};
+use crate::text::GlyphCache;
+use crate::texture::TextureBinding;
+use crate::texture_registry::{TextureRegistry, GLYPH_ATLAS_INDEX};
 use crate::time::TimeManager;
 use crate::ui::Ui;
+use crate::upscale::Upscaler;
 use crate::vert_buffers::{Instance, TexCoords, Vertex};
 
 pub struct EngineBuilder {
@@ -21,12 +27,21 @@ pub struct EngineBuilder {
 
     show_engine_ui: bool,
 
-    // true recommended for pixel art, otherwise keep false
+    enable_frame_capture: bool,
+    screenshot_key: Option<Button>,
+
+    // true recommended for pixel art, otherwise keep false. Only the default, every texture can pick its own
     use_near_filter_mode: bool,
 
+    camera_movement_speed: Option<f32>,
+
     reset_rate: Option<f64>,
     target_fps: Option<u32>,
     target_tps: Option<u32>,
+
+    post_effects: Vec<BuiltinEffect>,
+
+    virtual_resolution: Option<(u32, u32)>,
 }
 impl EngineBuilder {
     pub fn new(win_size: Vec32) -> Self {
@@ -38,11 +53,20 @@ impl EngineBuilder {
 
             show_engine_ui: false,
 
+            enable_frame_capture: false,
+            screenshot_key: None,
+
             use_near_filter_mode: false,
 
+            camera_movement_speed: None,
+
             reset_rate: None,
             target_fps: None,
             target_tps: None,
+
+            post_effects: vec![],
+
+            virtual_resolution: None,
         }
     }
     pub fn set_window_to_be_resizable(mut self) -> Self {
@@ -53,10 +77,27 @@ impl EngineBuilder {
         self.show_engine_ui = true;
         self
     }
+    /// Keeps a copy of every rendered frame so `Engine::capture_frame` works with a window.
+    /// Headless engines can always capture frames.
+    pub fn enable_frame_capture(mut self) -> Self {
+        self.enable_frame_capture = true;
+        self
+    }
+    /// Saves a screenshot to the working directory whenever `key` is pressed, enables frame capture.
+    pub fn with_screenshot_key(mut self, key: Button) -> Self {
+        self.enable_frame_capture = true;
+        self.screenshot_key = Some(key);
+        self
+    }
     pub fn use_near_filter_mode(mut self) -> Self {
         self.use_near_filter_mode = true;
         self
     }
+    /// Lets the camera be moved with WASD at `speed` world units per second.
+    pub fn enable_camera_movement(mut self, speed: f32) -> Self {
+        self.camera_movement_speed = Some(speed);
+        self
+    }
     pub fn enable_average_tps_and_set_reset_rate(mut self, reset_rate: Option<f64>) -> Self {
         self.reset_rate = reset_rate;
         self
@@ -74,12 +115,22 @@ impl EngineBuilder {
         self
     }
     pub fn with_background_color(mut self, color: crate::minor_types::Color) -> Self {
-        self.win_background_color = wgpu::Color {
-            r: color.r / 255.,
-            g: color.g / 255.,
-            b: color.b / 255.,
-            a: color.a / 255.,
-        };
+        self.win_background_color = color.to_wgpu();
+        self
+    }
+
+    /// Runs a built-in effect from the start, after the ones added before. The effects can be found in
+    /// `Engine::get_post_effects` in the same order, to change them at runtime.
+    pub fn with_post_effect(mut self, effect: BuiltinEffect) -> Self {
+        self.post_effects.push(effect);
+        self
+    }
+
+    /// Renders the scene at `width`x`height` pixels and scales it up to the window by a whole number, with black
+    /// bars around it. Cameras, `Engine::screen_to_world` and `Input::get_cursor_pos` work in these pixels.
+    /// Recommended for pixel art together with `use_near_filter_mode`, so sprites don't shimmer when they move.
+    pub fn with_virtual_resolution(mut self, width: u32, height: u32) -> Self {
+        self.virtual_resolution = Some((width.max(1), height.max(1)));
         self
     }
 
@@ -94,13 +145,7 @@ impl EngineBuilder {
 
         let win_size = window.inner_size();
 
-        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            dx12_shader_compiler: Default::default(), // sudo sysctl dev.i915.perf_stream_paranoid=0
-            flags: wgpu::InstanceFlags::default(),
-            gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
-        });
+        let instance = create_instance();
 
         // State owns the window so this should be safe.
         let surface = unsafe { instance.create_surface(&window) }.expect("Failed to init surface");
@@ -111,9 +156,65 @@ impl EngineBuilder {
         let surface_caps = surface.get_capabilities(&adapter);
         let surface_format = create_surface_format(&surface_caps);
 
-        let config = create_config(&surface_format, win_size, &surface_caps);
+        let config = create_config(
+            &surface_format,
+            win_size,
+            &surface_caps,
+            self.enable_frame_capture,
+        );
         surface.configure(&device, &config);
 
+        let scale_factor = window.scale_factor();
+        let canvas = Canvas::Window { window, surface };
+        self.build_engine(
+            canvas,
+            device,
+            queue,
+            config,
+            scale_factor,
+            Sound::new(true),
+        )
+    }
+
+    /// Builds an engine without a window that renders into an offscreen texture of `win_size`,
+    /// preferring a software/fallback adapter so it can run in CI and tests.
+    /// Drive it with `Engine::run_headless` instead of `Engine::start_loop`.
+    pub async fn build_headless(&mut self) -> Engine {
+        let win_size = PhysicalSize::new(self.win_size.x as u32, self.win_size.y as u32);
+
+        let instance = create_instance();
+        let adapter = create_headless_adapter(&instance).await;
+        let (device, queue) = create_device_and_queue(&adapter).await;
+
+        let config = create_headless_config(win_size);
+        let texture = capture::create_frame_texture(&device, &config, "Headless Texture");
+
+        let canvas = Canvas::Headless {
+            texture,
+            started: false,
+        };
+        self.build_engine(canvas, device, queue, config, 1., Sound::new_muted())
+    }
+
+    fn build_engine(
+        &mut self,
+        canvas: Canvas,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        scale_factor: f64,
+        sound: Sound,
+    ) -> Engine {
+        let win_size = PhysicalSize::new(config.width, config.height);
+
+        // Frames of a window are copied into frame_capture, a headless texture can be read directly
+        let frame_capture = match canvas {
+            Canvas::Window { .. } if config.usage.contains(wgpu::TextureUsages::COPY_SRC) => Some(
+                capture::create_frame_texture(&device, &config, "Frame Capture Texture"),
+            ),
+            _ => None,
+        };
+
         // Swap target_fps and target_tps because this way we use loop_helper which is more consistent
         if self.target_fps.is_some() && self.target_tps.is_none() {
             let temp = self.target_fps;
@@ -128,44 +229,32 @@ impl EngineBuilder {
         let time =
             crate::time::TimeManager::new(self.reset_rate, target_tps, self.target_tps.is_some());
 
-        let tex_bind_layout = texture::create_bind_group_layout(&device, 0);
-        let camera = Camera::new(false);
-        let camera_buffer = camera::create_buffer(&device, camera.uniform);
-        let camera_bind_group_layout = camera::create_bind_group_layout(&device);
-        let camera_bind_group =
-            camera::create_bind_group(&device, &camera_buffer, &camera_bind_group_layout);
-
-        let window_size_uniform = WindowUniform {
-            size: [1. / self.win_size.x as f32, 1. / self.win_size.y as f32],
+        // The device only has the features for binding arrays if the adapter supports them
+        let texture_binding = TextureBinding::from_features(device.features());
+
+        // Until the game uses textures, only the textures of the engine are registered
+        let mut textures = TextureRegistry::new(&device, &queue, texture_binding, config.format);
+        let atlas_index = textures.get_indices().reserved(GLYPH_ATLAS_INDEX);
+        let glyph_cache = GlyphCache::new(&device, &queue, atlas_index);
+        let materials = Materials::new(&device);
+        let post_effects = PostEffects::new(&device, config.format);
+        let upscaler = self
+            .virtual_resolution
+            .map(|(width, height)| Upscaler::new(&device, config.format, width, height));
+        // Cameras show the whole scene, which has the virtual resolution if there is one
+        let scene_size = match self.virtual_resolution {
+            Some((width, height)) => vec2(width as f32, height as f32),
+            None => self.win_size,
         };
-        let window_size_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("window size buffer"),
-            contents: bytemuck::cast_slice(&[window_size_uniform]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-        let window_bind_group_layout = create_win_layout(&device);
-        let window_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &window_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: window_size_buffer.as_entire_binding(),
-            }],
-            label: Some("camera_bind_group"),
-        });
-
+        textures.add(glyph_cache.get_texture());
+        let mut world_camera = Camera::new(scene_size);
+        world_camera.set_movement_speed(self.camera_movement_speed);
+        let layers = vec![
+            Layer::new(&device, WORLD_LAYER, world_camera, 0),
+            Layer::new(&device, SCREEN_LAYER, Camera::new(scene_size), 1),
+        ];
         let instances = vec![];
         let instance_buffer = super::vert_buffers::create_inst_buffer(&device, &instances);
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
-
-        let render_pipeline_layout = create_render_pipeline_layout(
-            &device,
-            &tex_bind_layout,
-            &camera_bind_group_layout,
-            &window_bind_group_layout,
-        );
-        let render_pipeline =
-            create_render_pipeline(&device, &render_pipeline_layout, &shader, &config);
-
         let (vertex_buffer, index_buffer) = super::vert_buffers::create_buffers(&device);
         let tex_coords_buffer =
             super::vert_buffers::create_tex_coords_buffer(&device, &[TexCoords::default()]);
@@ -174,37 +263,34 @@ impl EngineBuilder {
         let platform = Platform::new(PlatformDescriptor {
             physical_width: self.win_size.y as u32,
             physical_height: self.win_size.x as u32,
-            scale_factor: window.scale_factor(),
+            scale_factor,
             font_definitions: egui::FontDefinitions::default(),
             style: Default::default(),
         });
 
         // We use the egui_wgpu_backend crate as the render backend.
-        let egui_rpass = egui_wgpu_backend::RenderPass::new(&device, surface_format, 1);
+        let egui_rpass = egui_wgpu_backend::RenderPass::new(&device, config.format, 1);
 
         let ui = Ui::new(platform, egui_rpass, self.show_engine_ui);
 
         let all_fields = AllFields {
             input: crate::prelude::Input::new(),
-            window,
-            win_bind_group: window_bind_group,
+            canvas,
             win_size,
 
             win_background_color: self.win_background_color,
-            surface,
             device,
             queue,
             config,
             tex_coords: vec![],
+            material_ids: vec![],
 
-            render_pipeline,
             vertex_buffer,
             index_buffer,
             tex_coords_buffer,
 
-            camera,
-            camera_bind_group,
-            camera_buffer,
+            layers,
+            current_layer: 0,
 
             instance_buffer,
             instances,
@@ -212,37 +298,54 @@ impl EngineBuilder {
 
             time,
 
-            tex_bind: None,
-            texture_amt_created: 0,
+            frame_capture,
+            screenshot_key: self.screenshot_key,
+
+            textures,
             use_near_filter_mode: self.use_near_filter_mode,
+            glyph_cache,
+            materials,
+            post_effects,
+            upscaler,
 
             ui,
 
             target_fps: self.target_fps,
 
-            sound: Sound::new(true),
+            sound,
         };
-        Engine::new(all_fields)
+        let mut engine = Engine::new(all_fields);
+        for effect in &self.post_effects {
+            let effect = engine.create_builtin_effect(effect.clone());
+            engine.add_post_effect(effect);
+        }
+        engine
     }
 }
 
-pub fn create_shader(device: &wgpu::Device) -> wgpu::ShaderModule {
-    device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"))
+pub fn create_shader(device: &wgpu::Device, texture_binding: TextureBinding) -> wgpu::ShaderModule {
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("shader.wgsl"),
+        source: wgpu::ShaderSource::Wgsl(get_shader_source(texture_binding).into()),
+    })
 }
 
-pub fn create_win_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        entries: &[wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::VERTEX,
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
-            count: None,
-        }],
-        label: Some("camera_bind_group_layout"),
+/// shader.wgsl with the declarations of the textures, materials append their code to it.
+pub fn get_shader_source(texture_binding: TextureBinding) -> String {
+    format!(
+        "{}\n{}",
+        include_str!("shader.wgsl"),
+        texture_binding.get_shader_source()
+    )
+}
+
+fn create_instance() -> wgpu::Instance {
+    // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        dx12_shader_compiler: Default::default(), // sudo sysctl dev.i915.perf_stream_paranoid=0
+        flags: wgpu::InstanceFlags::default(),
+        gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
     })
 }
 
@@ -257,14 +360,53 @@ async fn create_adapter(instance: &wgpu::Instance, surface: &wgpu::Surface) -> w
         .expect("Failed to create adapter")
 }
 
+async fn create_headless_adapter(instance: &wgpu::Instance) -> wgpu::Adapter {
+    // Prefer a software adapter so headless runs are reproducible, but take any adapter if there is none
+    let fallback = instance
+        .request_adapter(&wgpu::RequestAdapterOptionsBase {
+            power_preference: wgpu::PowerPreference::LowPower,
+            force_fallback_adapter: true,
+            compatible_surface: None,
+        })
+        .await;
+    match fallback {
+        Some(adapter) => adapter,
+        None => instance
+            .request_adapter(&wgpu::RequestAdapterOptionsBase {
+                power_preference: wgpu::PowerPreference::LowPower,
+                force_fallback_adapter: false,
+                compatible_surface: None,
+            })
+            .await
+            .expect("Failed to create headless adapter"),
+    }
+}
+
 async fn create_device_and_queue(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
-    let limits = wgpu::Limits {
-        max_sampled_textures_per_shader_stage: 1024,
-        ..Default::default()
+    // GL backends and software rasterizers often can't index into arrays of textures
+    let texture_binding = TextureBinding::from_features(adapter.features());
+    let features = match texture_binding {
+        TextureBinding::Array => TextureBinding::array_features(),
+        TextureBinding::Single => {
+            log::warn!(
+                "binding arrays of textures are not supported, using a draw call per texture"
+            );
+            wgpu::Features::empty()
+        }
     };
 
-    let mut features = wgpu::Features::TEXTURE_BINDING_ARRAY;
-    features.extend(wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING);
+    // The defaults where the adapter meets them, lower limits only for GL and old GPUs that don't
+    let supported = adapter.limits();
+    let base = [wgpu::Limits::default(), wgpu::Limits::downlevel_defaults()]
+        .into_iter()
+        .find(|limits| limits.check_limits(&supported))
+        .unwrap_or_else(wgpu::Limits::downlevel_webgl2_defaults);
+    let limits = wgpu::Limits {
+        max_sampled_textures_per_shader_stage: supported
+            .max_sampled_textures_per_shader_stage
+            .min(1024),
+        ..base.using_resolution(supported)
+    };
 
     adapter
         .request_device(
@@ -292,9 +434,19 @@ fn create_config(
     surface_format: &wgpu::TextureFormat,
     size: PhysicalSize<u32>,
     surface_caps: &wgpu::SurfaceCapabilities,
+    enable_frame_capture: bool,
 ) -> wgpu::SurfaceConfiguration {
+    let mut usage = wgpu::TextureUsages::RENDER_ATTACHMENT;
+    if enable_frame_capture {
+        if surface_caps.usages.contains(wgpu::TextureUsages::COPY_SRC) {
+            usage |= wgpu::TextureUsages::COPY_SRC;
+        } else {
+            log::warn!("frame capture is not supported by this surface");
+        }
+    }
+
     wgpu::SurfaceConfiguration {
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        usage,
         format: *surface_format,
         width: size.width,
         height: size.height,
@@ -304,15 +456,27 @@ fn create_config(
     }
 }
 
+fn create_headless_config(size: PhysicalSize<u32>) -> wgpu::SurfaceConfiguration {
+    // There is no surface when headless, the config only describes the offscreen texture
+    wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        width: size.width,
+        height: size.height,
+        present_mode: wgpu::PresentMode::Fifo,
+        alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+        view_formats: vec![],
+    }
+}
+
+/// The layouts of the textures and the camera, followed by the one of the material parameters for materials.
 pub fn create_render_pipeline_layout(
     device: &wgpu::Device,
-    tex_layout: &wgpu::BindGroupLayout,
-    cam_layout: &wgpu::BindGroupLayout,
-    window_layout: &wgpu::BindGroupLayout,
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
 ) -> wgpu::PipelineLayout {
     device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Render Pipeline Layout"),
-        bind_group_layouts: &[tex_layout, cam_layout, window_layout],
+        bind_group_layouts,
         push_constant_ranges: &[],
     })
 }
@@ -321,7 +485,8 @@ pub fn create_render_pipeline(
     device: &wgpu::Device,
     render_pipeline_layout: &wgpu::PipelineLayout,
     shader: &wgpu::ShaderModule,
-    config: &wgpu::SurfaceConfiguration,
+    fs_entry_point: &str,
+    format: wgpu::TextureFormat,
 ) -> wgpu::RenderPipeline {
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some("Render Pipeline"),
@@ -333,9 +498,9 @@ pub fn create_render_pipeline(
         },
         fragment: Some(wgpu::FragmentState {
             module: shader,
-            entry_point: "fs_main",
+            entry_point: fs_entry_point,
             targets: &[Some(wgpu::ColorTargetState {
-                format: config.format,
+                format,
                 blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                 write_mask: wgpu::ColorWrites::ALL,
             })],
@@ -344,7 +509,8 @@ pub fn create_render_pipeline(
             topology: wgpu::PrimitiveTopology::TriangleList,
             strip_index_format: None,
             front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
+            // Flipped sprites have their winding order reversed and must not get culled
+            cull_mode: None,
             // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
             polygon_mode: wgpu::PolygonMode::Fill,
             // Requires Features::DEPTH_CLIP_CONTROL
@@ -365,33 +531,36 @@ pub fn create_render_pipeline(
 pub struct AllFields {
     pub input: crate::prelude::Input,
 
-    pub window: winit::window::Window,
+    pub canvas: Canvas,
     pub win_size: winit::dpi::PhysicalSize<u32>,
     pub win_background_color: wgpu::Color,
-    pub win_bind_group: wgpu::BindGroup,
 
-    pub surface: wgpu::Surface,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
 
-    pub render_pipeline: wgpu::RenderPipeline,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub instance_buffer: wgpu::Buffer,
-    pub camera_buffer: wgpu::Buffer,
     pub tex_coords_buffer: wgpu::Buffer,
 
     pub tex_coords: Vec<TexCoords>,
+    pub material_ids: Vec<u32>,
     pub instances: Vec<Instance>,
     pub instances_rendered: usize,
 
-    pub tex_bind: Option<wgpu::BindGroup>,
-    pub texture_amt_created: u32,
+    pub frame_capture: Option<wgpu::Texture>,
+    pub screenshot_key: Option<Button>,
+
+    pub textures: TextureRegistry,
     pub use_near_filter_mode: bool,
+    pub glyph_cache: GlyphCache,
+    pub materials: Materials,
+    pub post_effects: PostEffects,
+    pub upscaler: Option<Upscaler>,
 
-    pub camera: Camera,
-    pub camera_bind_group: wgpu::BindGroup,
+    pub layers: Vec<Layer>,
+    pub current_layer: usize,
 
     pub time: TimeManager,
 
@@ -0,0 +1,398 @@
+//! Test support for rendering a `Manager` headless and comparing the result against reference PNGs.
+//!
+//! References live in `<reference_dir>/<name>.png`. When a comparison fails the rendered frame and a diff
+//! image are written next to it as `<name>.actual.png` and `<name>.diff.png`. Run with `GOODMAN_BLESS=1`
+//! to (re)write the references instead of comparing against them.
+//!
+//! Only compiled with the `golden` feature, games don't ship it.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+
+use crate::prelude::{Button, EngineBuilder, Manager, Vec64};
+
+/// Input that gets applied right before the `update` of a given tick.
+#[derive(Debug, Clone, Copy)]
+pub enum ScriptedInput {
+    Press(Button),
+    Release(Button),
+    MoveCursor(Vec64),
+    Scroll(i8),
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    MissingReference(PathBuf),
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    Mismatch {
+        differing_pixels: u32,
+        diff_path: PathBuf,
+    },
+    Image(String),
+}
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoldenError::MissingReference(path) => write!(
+                f,
+                "reference {} does not exist, run with GOODMAN_BLESS=1 to create it",
+                path.display()
+            ),
+            GoldenError::SizeMismatch { expected, actual } => write!(
+                f,
+                "frame is {}x{} but the reference is {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            GoldenError::Mismatch {
+                differing_pixels,
+                diff_path,
+            } => write!(
+                f,
+                "{differing_pixels} pixels differ from the reference, see {}",
+                diff_path.display()
+            ),
+            GoldenError::Image(e) => write!(f, "{e}"),
+        }
+    }
+}
+impl std::error::Error for GoldenError {}
+
+/// How far a frame may be off from its reference and still match, which leaves room for the small
+/// differences between GPUs, drivers and software rasterizers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    /// The maximum difference of the red, green, blue and alpha channel for two pixels to count as equal
+    pub channels: [u8; 4],
+    /// The share of pixels from 0 to 1 that may differ anyway, for edges that get covered differently
+    pub differing_share: f32,
+}
+impl Tolerance {
+    /// Every channel may be off by `max`, but no pixel by more.
+    pub fn from_channel_max(max: u8) -> Self {
+        Self {
+            channels: [max; 4],
+            differing_share: 0.,
+        }
+    }
+}
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            channels: [8; 4],
+            differing_share: 0.002,
+        }
+    }
+}
+
+pub struct GoldenTest {
+    name: String,
+    reference_dir: PathBuf,
+    ticks: u32,
+    delta_t: f64,
+    tolerance: Tolerance,
+    script: Vec<(u32, ScriptedInput)>,
+    bless: bool,
+}
+impl GoldenTest {
+    pub fn new(name: &str, reference_dir: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            reference_dir: PathBuf::from(reference_dir),
+            ticks: 1,
+            delta_t: 1. / 60.,
+            tolerance: Tolerance::default(),
+            script: vec![],
+            bless: std::env::var_os("GOODMAN_BLESS").is_some(),
+        }
+    }
+    pub fn with_ticks(mut self, ticks: u32) -> Self {
+        self.ticks = ticks;
+        self
+    }
+    pub fn with_delta_t(mut self, delta_t: f64) -> Self {
+        self.delta_t = delta_t;
+        self
+    }
+    /// How far the frame may be off from the reference, `Tolerance::default()` if not set.
+    pub fn with_tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+    pub fn with_input(mut self, tick: u32, input: ScriptedInput) -> Self {
+        self.script.push((tick, input));
+        self
+    }
+    /// Whether to write the reference instead of comparing against it, `GOODMAN_BLESS` being set by default.
+    pub fn with_bless(mut self, bless: bool) -> Self {
+        self.bless = bless;
+        self
+    }
+
+    /// Builds a headless engine from `builder`, creates `T` and returns the frame rendered on the last tick.
+    pub async fn render<T: Manager>(&self, builder: &mut EngineBuilder) -> RgbaImage {
+        let mut engine = builder.build_headless().await;
+        let mut manager = T::new(&mut engine);
+
+        engine.run_headless_with_input(&mut manager, self.ticks, self.delta_t, |tick, input| {
+            for (_, scripted) in self.script.iter().filter(|(t, _)| *t == tick) {
+                match *scripted {
+                    ScriptedInput::Press(button) => input.set_button(button, true),
+                    ScriptedInput::Release(button) => input.set_button(button, false),
+                    ScriptedInput::MoveCursor(pos) => input.set_cursor_pos(pos),
+                    ScriptedInput::Scroll(movement) => input.set_wheel_movement(movement),
+                }
+            }
+        })
+    }
+
+    /// Renders `T` and compares the frame against the reference image.
+    pub async fn check<T: Manager>(&self, builder: &mut EngineBuilder) -> Result<(), GoldenError> {
+        let frame = self.render::<T>(builder).await;
+        self.check_frame(&frame)
+    }
+
+    /// Compares an already rendered frame against the reference image, or writes it as the reference when
+    /// blessing.
+    pub fn check_frame(&self, frame: &RgbaImage) -> Result<(), GoldenError> {
+        let reference_path = self.path("png");
+
+        if self.bless {
+            std::fs::create_dir_all(&self.reference_dir)
+                .map_err(|e| GoldenError::Image(e.to_string()))?;
+            return frame
+                .save(&reference_path)
+                .map_err(|e| GoldenError::Image(e.to_string()));
+        }
+        if !reference_path.exists() {
+            return Err(GoldenError::MissingReference(reference_path));
+        }
+
+        let reference = image::open(&reference_path)
+            .map_err(|e| GoldenError::Image(e.to_string()))?
+            .to_rgba8();
+        if reference.dimensions() != frame.dimensions() {
+            return Err(GoldenError::SizeMismatch {
+                expected: reference.dimensions(),
+                actual: frame.dimensions(),
+            });
+        }
+
+        match compare_images(frame, &reference, self.tolerance) {
+            Ok(()) => Ok(()),
+            Err((differing_pixels, diff)) => {
+                let diff_path = self.path("diff.png");
+                frame
+                    .save(self.path("actual.png"))
+                    .and_then(|_| diff.save(&diff_path))
+                    .map_err(|e| GoldenError::Image(e.to_string()))?;
+                Err(GoldenError::Mismatch {
+                    differing_pixels,
+                    diff_path,
+                })
+            }
+        }
+    }
+
+    fn path(&self, extension: &str) -> PathBuf {
+        self.reference_dir
+            .join(format!("{}.{extension}", self.name))
+    }
+}
+
+/// Compares two images, on failure returns the amount of differing pixels and a diff image in which differing
+/// pixels are red and equal pixels are a faded grayscale of `reference`. Images of different sizes differ in
+/// every pixel.
+pub fn compare_images(
+    actual: &RgbaImage,
+    reference: &RgbaImage,
+    tolerance: Tolerance,
+) -> Result<(), (u32, RgbaImage)> {
+    if actual.dimensions() != reference.dimensions() {
+        let diff = RgbaImage::from_pixel(
+            reference.width(),
+            reference.height(),
+            Rgba([255, 0, 0, 255]),
+        );
+        return Err((reference.width() * reference.height(), diff));
+    }
+
+    let mut differing_pixels = 0;
+    let mut diff = RgbaImage::new(reference.width(), reference.height());
+
+    for (x, y, expected) in reference.enumerate_pixels() {
+        let pixel = actual.get_pixel(x, y);
+        let differs = pixel
+            .0
+            .iter()
+            .zip(expected.0.iter())
+            .zip(tolerance.channels.iter())
+            .any(|((a, b), max)| a.abs_diff(*b) > *max);
+
+        if differs {
+            differing_pixels += 1;
+            diff.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+        } else {
+            let [r, g, b, _] = expected.0;
+            let gray = ((r as u32 + g as u32 + b as u32) / 6) as u8;
+            diff.put_pixel(x, y, Rgba([gray, gray, gray, 255]));
+        }
+    }
+
+    let allowed = (reference.width() * reference.height()) as f32 * tolerance.differing_share;
+    if differing_pixels as f32 <= allowed {
+        Ok(())
+    } else {
+        Err((differing_pixels, diff))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, rgba: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba(rgba))
+    }
+
+    // a directory of its own for every test, as tests run in parallel
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("goodman_golden_{}_{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn bless(dir: &std::path::Path, frame: &RgbaImage) {
+        let test = GoldenTest::new("frame", dir.to_str().unwrap()).with_bless(true);
+        test.check_frame(frame).unwrap();
+    }
+
+    #[test]
+    fn equal_images_match() {
+        let image = solid(4, 3, [10, 20, 30, 255]);
+        assert!(compare_images(&image, &image, Tolerance::from_channel_max(0)).is_ok());
+    }
+
+    #[test]
+    fn tolerance_is_inclusive() {
+        let reference = solid(2, 2, [100, 100, 100, 255]);
+        let actual = solid(2, 2, [102, 98, 100, 255]);
+        assert!(compare_images(&actual, &reference, Tolerance::from_channel_max(2)).is_ok());
+
+        let tolerance = Tolerance::from_channel_max(1);
+        let (differing_pixels, _) = compare_images(&actual, &reference, tolerance).unwrap_err();
+        assert_eq!(differing_pixels, 4);
+    }
+
+    #[test]
+    fn tolerance_is_per_channel() {
+        let reference = solid(2, 2, [100, 100, 100, 255]);
+        let actual = solid(2, 2, [100, 100, 110, 255]);
+        let tolerance = Tolerance {
+            channels: [0, 0, 10, 0],
+            differing_share: 0.,
+        };
+        assert!(compare_images(&actual, &reference, tolerance).is_ok());
+
+        let actual = solid(2, 2, [100, 101, 100, 255]);
+        assert!(compare_images(&actual, &reference, tolerance).is_err());
+    }
+
+    #[test]
+    fn a_share_of_pixels_may_differ() {
+        let reference = solid(10, 10, [0, 0, 0, 255]);
+        let mut actual = reference.clone();
+        actual.put_pixel(3, 3, Rgba([255, 255, 255, 255]));
+        let tolerance = Tolerance {
+            channels: [0; 4],
+            differing_share: 0.01,
+        };
+        assert!(compare_images(&actual, &reference, tolerance).is_ok());
+
+        actual.put_pixel(4, 4, Rgba([255, 255, 255, 255]));
+        let (differing_pixels, _) = compare_images(&actual, &reference, tolerance).unwrap_err();
+        assert_eq!(differing_pixels, 2);
+    }
+
+    #[test]
+    fn diff_marks_differing_pixels_red() {
+        let reference = solid(3, 1, [60, 60, 60, 255]);
+        let mut actual = reference.clone();
+        actual.put_pixel(1, 0, Rgba([60, 60, 60, 0]));
+
+        let tolerance = Tolerance::from_channel_max(2);
+        let (differing_pixels, diff) = compare_images(&actual, &reference, tolerance).unwrap_err();
+        assert_eq!(differing_pixels, 1);
+        assert_eq!(diff.get_pixel(1, 0).0, [255, 0, 0, 255]);
+        assert_eq!(diff.get_pixel(0, 0).0, [30, 30, 30, 255]);
+    }
+
+    #[test]
+    fn different_sizes_differ_everywhere() {
+        let reference = solid(4, 4, [0, 0, 0, 255]);
+        let (differing_pixels, diff) = compare_images(
+            &solid(2, 4, [0, 0, 0, 255]),
+            &reference,
+            Tolerance::from_channel_max(255),
+        )
+        .unwrap_err();
+        assert_eq!(differing_pixels, 16);
+        assert_eq!(diff.dimensions(), (4, 4));
+    }
+
+    #[test]
+    fn check_frame_reports_size_mismatch() {
+        let dir = temp_dir("size");
+        bless(&dir, &solid(4, 4, [0; 4]));
+
+        let test = GoldenTest::new("frame", dir.to_str().unwrap()).with_bless(false);
+        match test.check_frame(&solid(4, 2, [0; 4])) {
+            Err(GoldenError::SizeMismatch { expected, actual }) => {
+                assert_eq!((expected, actual), ((4, 4), (4, 2)));
+            }
+            result => panic!("expected a size mismatch, got {result:?}"),
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn check_frame_writes_actual_and_diff_on_mismatch() {
+        let dir = temp_dir("mismatch");
+        bless(&dir, &solid(4, 4, [0, 0, 0, 255]));
+
+        let test = GoldenTest::new("frame", dir.to_str().unwrap()).with_bless(false);
+        assert!(test.check_frame(&solid(4, 4, [1, 1, 1, 255])).is_ok());
+        match test.check_frame(&solid(4, 4, [200, 0, 0, 255])) {
+            Err(GoldenError::Mismatch {
+                differing_pixels,
+                diff_path,
+            }) => {
+                assert_eq!(differing_pixels, 16);
+                assert_eq!(diff_path, dir.join("frame.diff.png"));
+                let diff = image::open(&diff_path).unwrap().to_rgba8();
+                assert_eq!(diff.get_pixel(0, 0).0, [255, 0, 0, 255]);
+                let actual = image::open(dir.join("frame.actual.png"))
+                    .unwrap()
+                    .to_rgba8();
+                assert_eq!(actual.get_pixel(0, 0).0, [200, 0, 0, 255]);
+            }
+            result => panic!("expected a mismatch, got {result:?}"),
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn check_frame_reports_missing_reference() {
+        let dir = temp_dir("missing");
+        let test = GoldenTest::new("frame", dir.to_str().unwrap()).with_bless(false);
+        assert!(matches!(
+            test.check_frame(&solid(1, 1, [0; 4])),
+            Err(GoldenError::MissingReference(path)) if path == dir.join("frame.png")
+        ));
+    }
+}
@@ -25,6 +25,7 @@ macro_rules! CreateInputStruct {
     ($($field_name: ident)*) => {
         pub struct Input {
             cursor_pos: Vec64,
+            cursor_world_pos: Vec64,
             mouse_wheel: i8, // wheel up = 1, down = -1, no movement = 0,
             $($field_name: Button,)*
         }
@@ -33,18 +34,40 @@ macro_rules! CreateInputStruct {
             pub(crate) fn new() -> Self {
                 Self {
                     cursor_pos: vec2(0., 0.),
+                    cursor_world_pos: vec2(0., 0.),
                     mouse_wheel: 0,
                     $($field_name: Button::new(),)*
                 }
             }
 
+            /// Returns the cursor position in physical pixels of the window, or in pixels of the virtual resolution
+            /// if the engine has one.
             pub fn get_cursor_pos(&self) -> Vec64 {
                 self.cursor_pos
             }
 
+            /// Returns the cursor position in the world as seen through the camera.
+            pub fn get_cursor_world_pos(&self) -> Vec64 {
+                self.cursor_world_pos
+            }
+
             pub fn get_wheel_movement(&self) -> i8 {
                 self.mouse_wheel // wheel up = 1, down = -1, no movement = 0,
             }
+
+            pub(crate) fn set_cursor_pos(&mut self, pos: Vec64) {
+                self.cursor_pos = pos;
+            }
+
+            pub(crate) fn set_cursor_world_pos(&mut self, pos: Vec64) {
+                self.cursor_world_pos = pos;
+            }
+
+            // only used by the scripted input of golden tests
+            #[cfg(feature = "golden")]
+            pub(crate) fn set_wheel_movement(&mut self, movement: i8) {
+                self.mouse_wheel = movement;
+            }
         }
     };
 }
@@ -152,6 +175,13 @@ macro_rules! is_button_pressed_or_held {
                     $(ButtonEnum::$button_enum => self.$button.pressed,)*
                 }
             }
+            // only used by the scripted input of golden tests
+            #[cfg(feature = "golden")]
+            pub(crate) fn set_button(&mut self, c: ButtonEnum, is_pressed: bool) {
+                match c {
+                    $(ButtonEnum::$button_enum => self.$button.set_both(is_pressed),)*
+                }
+            }
         }
     };
 }
@@ -165,6 +195,7 @@ is_button_pressed_or_held!(
     RControl,r_control RShift,r_shift RAlt,r_alt LControl,l_control LShift,l_shift LAlt,l_alt
 );
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ButtonEnum {
     LeftMouse,
     RightMouse,
@@ -0,0 +1,68 @@
+use std::ops::Range;
+
+use crate::{
+    camera::{self, Camera, CameraUniform},
+    render_target::LayerTarget,
+    vert_buffers::{Instance, TexCoords},
+};
+
+pub const WORLD_LAYER: &str = "world";
+pub const SCREEN_LAYER: &str = "screen";
+
+/// A group of sprites drawn with its own camera and viewport, layers are drawn in order of creation
+/// with the screen layer always on top.
+pub(crate) struct Layer {
+    pub name: String,
+    pub camera: Camera,
+    pub camera_buffer: wgpu::Buffer,
+    pub camera_bind_group: wgpu::BindGroup,
+    // index of the layer whose sprites get drawn, the layer itself unless it's a view of another layer
+    pub source: usize,
+    pub instances: Vec<Instance>,
+    pub tex_coords: Vec<TexCoords>,
+    // z value of every instance, sprites with a higher z get drawn on top
+    pub z: Vec<f32>,
+    // material id of every instance, decides which pipeline draws it
+    pub material_ids: Vec<u32>,
+    // where the sprites of this layer are in the instance buffer this frame
+    pub instance_range: Range<u32>,
+    // the render target the layer draws into instead of the frame, these layers come after the screen layer
+    pub target: Option<LayerTarget>,
+}
+impl Layer {
+    pub fn new(device: &wgpu::Device, name: &str, camera: Camera, source: usize) -> Self {
+        // The uniform gets written before the first frame because a new camera is always outdated
+        let camera_buffer = camera::create_buffer(device, CameraUniform::new());
+        let camera_bind_group_layout = camera::create_bind_group_layout(device);
+        let camera_bind_group =
+            camera::create_bind_group(device, &camera_buffer, &camera_bind_group_layout);
+
+        Self {
+            name: name.to_string(),
+            camera,
+            camera_buffer,
+            camera_bind_group,
+            source,
+            instances: vec![],
+            tex_coords: vec![],
+            z: vec![],
+            material_ids: vec![],
+            instance_range: 0..0,
+            target: None,
+        }
+    }
+
+    /// Sorts the sprites of this frame by their z value, sprites with the same z keep the order they were submitted in.
+    pub fn sort_by_z(&mut self) {
+        if self.z.windows(2).all(|w| w[0] <= w[1]) {
+            return;
+        }
+
+        let mut order: Vec<usize> = (0..self.z.len()).collect();
+        // sort_by is stable
+        order.sort_by(|&a, &b| self.z[a].total_cmp(&self.z[b]));
+        self.instances = order.iter().map(|&i| self.instances[i]).collect();
+        self.tex_coords = order.iter().map(|&i| self.tex_coords[i]).collect();
+        self.material_ids = order.iter().map(|&i| self.material_ids[i]).collect();
+    }
+}
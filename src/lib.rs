@@ -1,12 +1,25 @@
+mod atlas;
+mod builtin_effect;
 mod camera;
+mod capture;
+mod dynamic_texture;
 mod engine;
 mod engine_builder;
+#[cfg(feature = "golden")]
+pub mod golden;
 mod input;
+mod layer;
+mod material;
 mod math;
 mod minor_types;
+mod post_effect;
 pub mod prelude;
+mod render_target;
 mod sound;
+mod text;
 mod texture;
+mod texture_registry;
 mod time;
 mod ui;
+mod upscale;
 mod vert_buffers;
@@ -0,0 +1,118 @@
+use wgpu::util::DeviceExt;
+
+/// The amount of floats every material gets as parameters.
+pub const MATERIAL_PARAM_AMT: usize = 16;
+
+/// A custom fragment shader that sprites can be drawn with by setting `DrawParams::material`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Material {
+    pub(crate) id: u32,
+}
+
+// Declarations available to the code of a material and the entry point calling it
+const MATERIAL_PRELUDE: &str = "
+@group(2) @binding(0)
+var<uniform> material_params: array<vec4<f32>, 4>;
+
+// the parameters set with Engine::set_material_params
+fn get_param(i: u32) -> f32 {
+    return material_params[i / 4u][i % 4u];
+}
+";
+const MATERIAL_ENTRY: &str = "
+@fragment
+fn fs_material(in: VertexOutput) -> @location(0) vec4<f32> {
+    return material(in, sample_texture(in.index, in.sampler_index, in.tex_coords));
+}
+";
+
+pub(crate) struct MaterialData {
+    pub shader: wgpu::ShaderModule,
+    params_buffer: wgpu::Buffer,
+    pub params_bind_group: wgpu::BindGroup,
+}
+
+/// All materials created so far, the id of a material is its index plus one, 0 is the default fragment shader.
+pub(crate) struct Materials {
+    pub params_layout: wgpu::BindGroupLayout,
+    list: Vec<MaterialData>,
+}
+impl Materials {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("material_bind_group_layout"),
+        });
+        Self {
+            params_layout,
+            list: vec![],
+        }
+    }
+
+    /// Appends the code of the material to the shader of the engine.
+    pub fn compose_source(engine_source: &str, fragment: &str) -> String {
+        format!("{engine_source}\n{MATERIAL_PRELUDE}\n{fragment}\n{MATERIAL_ENTRY}")
+    }
+
+    pub fn add(&mut self, device: &wgpu::Device, shader: wgpu::ShaderModule) -> Material {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Material Params Buffer"),
+            contents: bytemuck::cast_slice(&[0f32; MATERIAL_PARAM_AMT]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.params_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            }],
+            label: Some("material_bind_group"),
+        });
+
+        self.list.push(MaterialData {
+            shader,
+            params_buffer,
+            params_bind_group,
+        });
+        Material {
+            id: self.list.len() as u32,
+        }
+    }
+
+    pub fn remove_last(&mut self) {
+        self.list.pop();
+    }
+
+    /// Returns None for the default fragment shader.
+    pub fn get(&self, id: u32) -> Option<&MaterialData> {
+        self.list.get(id.checked_sub(1)? as usize)
+    }
+
+    pub fn set_params(
+        &self,
+        queue: &wgpu::Queue,
+        material: Material,
+        params: &[f32],
+    ) -> Result<(), String> {
+        if params.len() > MATERIAL_PARAM_AMT {
+            return Err(format!(
+                "a material has at most {MATERIAL_PARAM_AMT} parameters, got {}",
+                params.len()
+            ));
+        }
+        let Some(data) = self.get(material.id) else {
+            return Err(format!("material {} does not exist", material.id));
+        };
+        queue.write_buffer(&data.params_buffer, 0, bytemuck::cast_slice(params));
+        Ok(())
+    }
+}
@@ -1,4 +1,11 @@
-use crate::{engine::Engine, input::Input, prelude::Rect32, sound::Sound};
+use crate::{
+    engine::Engine,
+    input::Input,
+    material::Material,
+    prelude::{Rect32, Vec32},
+    sound::Sound,
+};
+use cgmath::vec2;
 pub trait Manager {
     fn new(engine: &mut Engine) -> Self;
     fn start(&mut self) {}
@@ -8,8 +15,23 @@ pub trait Manager {
 
 #[derive(Debug, Clone, Copy)]
 pub struct DrawParams {
+    /// Rotation in degrees around `origin`
     pub rotation: f32,
     pub source: Option<Rect32>,
+    /// Gets multiplied with the color of the texture, the alpha makes the texture transparent
+    pub color: Color,
+    /// Point of the rect that gets rotated and scaled around, (0, 0) is the top left and (1, 1) the bottom right
+    pub origin: Vec32,
+    /// Scales the rect around `origin`, negative values flip it
+    pub scale: Vec32,
+    /// Mirrors the texture horizontally within its rect
+    pub flip_x: bool,
+    /// Mirrors the texture vertically within its rect
+    pub flip_y: bool,
+    /// Sprites with a higher z get drawn on top of sprites in the same layer, regardless of the order they're rendered in
+    pub z: f32,
+    /// Draws the texture with a custom fragment shader instead of the default one
+    pub material: Option<Material>,
 }
 impl DrawParams {
     pub fn from_source(source: Rect32) -> Self {
@@ -18,16 +40,72 @@ impl DrawParams {
             ..Default::default()
         }
     }
+    pub fn from_rotation(degrees: f32) -> Self {
+        DrawParams {
+            rotation: degrees,
+            ..Default::default()
+        }
+    }
+    pub fn from_rotation_rad(radians: f32) -> Self {
+        Self::from_rotation(radians.to_degrees())
+    }
+    /// Sets the rotation in radians instead of degrees.
+    pub fn set_rotation_rad(&mut self, radians: f32) {
+        self.rotation = radians.to_degrees();
+    }
+    pub fn from_z(z: f32) -> Self {
+        DrawParams {
+            z,
+            ..Default::default()
+        }
+    }
+    pub fn from_color(color: Color) -> Self {
+        DrawParams {
+            color,
+            ..Default::default()
+        }
+    }
+    pub fn from_material(material: Material) -> Self {
+        DrawParams {
+            material: Some(material),
+            ..Default::default()
+        }
+    }
 }
 impl Default for DrawParams {
     fn default() -> Self {
         Self {
             rotation: 0.,
             source: None,
+            color: Color::WHITE,
+            origin: vec2(0., 0.),
+            scale: vec2(1., 1.),
+            flip_x: false,
+            flip_y: false,
+            z: 0.,
+            material: None,
         }
     }
 }
 
+/// The color and z of a shape, a `Color` can be passed wherever these are taken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapeParams {
+    pub color: Color,
+    /// Shapes with a higher z get drawn on top of sprites and shapes in the same layer
+    pub z: f32,
+}
+impl ShapeParams {
+    pub fn new(color: Color, z: f32) -> Self {
+        Self { color, z }
+    }
+}
+impl From<Color> for ShapeParams {
+    fn from(color: Color) -> Self {
+        Self { color, z: 0. }
+    }
+}
+
 pub struct Animation<T: Copy> {
     frames: Vec<T>,
     current_frame: usize,
@@ -60,12 +138,6 @@ impl<T: Copy> Animation<T> {
     }
 }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct WindowUniform {
-    pub size: [f32; 2],
-}
-
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Color {
     /// Red component of the color
@@ -84,6 +156,25 @@ impl Color {
         Self { r, g, b, a }
     }
 
+    pub(crate) fn to_normalized(self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a].map(|c| (c / 255.) as f32)
+    }
+    pub(crate) fn to_wgpu(self) -> wgpu::Color {
+        wgpu::Color {
+            r: self.r / 255.,
+            g: self.g / 255.,
+            b: self.b / 255.,
+            a: self.a / 255.,
+        }
+    }
+    pub(crate) fn to_rgba8(self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a].map(|c| c.round().clamp(0., 255.) as u8)
+    }
+    pub(crate) fn from_rgba8(rgba: [u8; 4]) -> Self {
+        let [r, g, b, a] = rgba.map(|c| c as f64);
+        Self { r, g, b, a }
+    }
+
     pub const TRANSPARENT: Self = Self {
         r: 0.0,
         g: 0.0,
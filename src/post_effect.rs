@@ -0,0 +1,440 @@
+use std::mem::{discriminant, Discriminant};
+
+use wgpu::util::DeviceExt;
+
+use crate::builtin_effect::{BuiltinEffect, Lut};
+
+/// The amount of floats every post effect gets as parameters.
+pub const POST_EFFECT_PARAM_AMT: usize = 16;
+
+/// A full-screen pass that runs on the rendered scene before the UI is drawn on top of it.
+/// Effects run in the order they were added with `Engine::add_post_effect`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PostEffect {
+    pub(crate) id: u32,
+}
+
+// Declarations available to the code of an effect, the entry points calling it come after the code
+const POST_PRELUDE: &str = "
+@group(0) @binding(0)
+var scene_sampler: sampler;
+@group(0) @binding(1)
+var scene: texture_2d<f32>;
+@group(1) @binding(0)
+var<uniform> effect_params: array<vec4<f32>, 4>;
+
+struct PostOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// the parameters set with Engine::set_post_effect_params
+fn get_param(i: u32) -> f32 {
+    return effect_params[i / 4u][i % 4u];
+}
+
+// the output of the previous pass, uv goes from (0, 0) at the top left to (1, 1) at the bottom right
+fn sample_scene(uv: vec2<f32>) -> vec4<f32> {
+    return textureSample(scene, scene_sampler, uv);
+}
+
+// in pixels
+fn get_scene_size() -> vec2<f32> {
+    return vec2<f32>(textureDimensions(scene));
+}
+
+// between linear colors and colors as they are stored in images
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    let low = c / 12.92;
+    let high = pow((c + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, c <= vec3<f32>(0.04045));
+}
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3<f32>(1. / 2.4)) - 0.055;
+    return select(high, low, c <= vec3<f32>(0.0031308));
+}
+";
+// The scene is read as linear colors only if the frame has an sRGB format, otherwise as stored in images
+const SRGB_SCENE_CONVERSION: &str = "
+fn scene_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return c;
+}
+fn linear_to_scene(c: vec3<f32>) -> vec3<f32> {
+    return c;
+}
+";
+const NON_SRGB_SCENE_CONVERSION: &str = "
+fn scene_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return srgb_to_linear(c);
+}
+fn linear_to_scene(c: vec3<f32>) -> vec3<f32> {
+    return linear_to_srgb(c);
+}
+";
+const POST_ENTRY: &str = "
+// a triangle covering the whole screen
+@vertex
+fn vs_post(@builtin(vertex_index) i: u32) -> PostOutput {
+    let pos = vec2<f32>(f32(i == 1u) * 4. - 1., f32(i == 2u) * 4. - 1.);
+    var out: PostOutput;
+    out.clip_position = vec4<f32>(pos, 0., 1.);
+    out.uv = vec2<f32>(pos.x * 0.5 + 0.5, 0.5 - pos.y * 0.5);
+    return out;
+}
+
+@fragment
+fn fs_post(in: PostOutput) -> @location(0) vec4<f32> {
+    return effect(in.uv, sample_scene(in.uv));
+}
+";
+
+struct EffectData {
+    pipeline: wgpu::RenderPipeline,
+    params_buffer: wgpu::Buffer,
+    params_bind_group: wgpu::BindGroup,
+    // which built-in effect this is, None for the ones with the code of the game
+    builtin: Option<Discriminant<BuiltinEffect>>,
+    // the table of a LUT effect, kept to tell whether a new one has to be uploaded
+    lut: Option<(Lut, wgpu::BindGroup)>,
+}
+
+// A texture the scene or an effect renders into, bound for the next effect to read
+struct Target {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+/// All post effects created so far and the chain of the ones in use. The scene only gets rendered into a
+/// texture of its own while the chain isn't empty, otherwise it goes straight into the frame.
+pub(crate) struct PostEffects {
+    format: wgpu::TextureFormat,
+    sampler: wgpu::Sampler,
+    scene_layout: wgpu::BindGroupLayout,
+    params_layout: wgpu::BindGroupLayout,
+    lut_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    lut_pipeline_layout: wgpu::PipelineLayout,
+    list: Vec<EffectData>,
+    chain: Vec<PostEffect>,
+    // the scene and the effects take turns reading from one and writing into the other
+    targets: Vec<Target>,
+    target_size: (u32, u32),
+}
+impl PostEffects {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Effect Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let scene_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("post_scene_bind_group_layout"),
+        });
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("post_params_bind_group_layout"),
+        });
+        let lut_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("post_lut_bind_group_layout"),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Effect Pipeline Layout"),
+            bind_group_layouts: &[&scene_layout, &params_layout],
+            push_constant_ranges: &[],
+        });
+        let lut_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("LUT Effect Pipeline Layout"),
+            bind_group_layouts: &[&scene_layout, &params_layout, &lut_layout],
+            push_constant_ranges: &[],
+        });
+
+        Self {
+            format,
+            sampler,
+            scene_layout,
+            params_layout,
+            lut_layout,
+            pipeline_layout,
+            lut_pipeline_layout,
+            list: vec![],
+            chain: vec![],
+            targets: vec![],
+            target_size: (0, 0),
+        }
+    }
+
+    /// Appends the code of the effect to the declarations it can use.
+    pub fn compose_source(&self, fragment: &str) -> String {
+        let conversion = match self.format.is_srgb() {
+            true => SRGB_SCENE_CONVERSION,
+            false => NON_SRGB_SCENE_CONVERSION,
+        };
+        format!("{POST_PRELUDE}\n{conversion}\n{fragment}\n{POST_ENTRY}")
+    }
+
+    /// `builtin` is the built-in effect `shader` was made for, if it's one.
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        builtin: Option<&BuiltinEffect>,
+    ) -> PostEffect {
+        let layout = match builtin {
+            Some(BuiltinEffect::Lut(_)) => &self.lut_pipeline_layout,
+            _ => &self.pipeline_layout,
+        };
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Post Effect Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_post",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_post",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: self.format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post Effect Params Buffer"),
+            contents: bytemuck::cast_slice(&[0f32; POST_EFFECT_PARAM_AMT]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.params_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            }],
+            label: Some("post_params_bind_group"),
+        });
+
+        self.list.push(EffectData {
+            pipeline,
+            params_buffer,
+            params_bind_group,
+            builtin: builtin.map(discriminant),
+            lut: None,
+        });
+        PostEffect {
+            id: self.list.len() as u32 - 1,
+        }
+    }
+
+    pub fn remove_last(&mut self) {
+        self.list.pop();
+    }
+
+    pub fn set_params(
+        &self,
+        queue: &wgpu::Queue,
+        effect: PostEffect,
+        params: &[f32],
+    ) -> Result<(), String> {
+        if params.len() > POST_EFFECT_PARAM_AMT {
+            return Err(format!(
+                "a post effect has at most {POST_EFFECT_PARAM_AMT} parameters, got {}",
+                params.len()
+            ));
+        }
+        let Some(data) = self.list.get(effect.id as usize) else {
+            return Err(format!("post effect {} does not exist", effect.id));
+        };
+        queue.write_buffer(&data.params_buffer, 0, bytemuck::cast_slice(params));
+        Ok(())
+    }
+
+    /// Sets the parameters of a built-in effect, `params` has to be the kind of effect it was created as.
+    pub fn set_builtin_params(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        effect: PostEffect,
+        params: &BuiltinEffect,
+    ) -> Result<(), String> {
+        match self.list.get(effect.id as usize) {
+            Some(data) if data.builtin == Some(discriminant(params)) => {}
+            Some(_) => {
+                let name = params.get_name();
+                return Err(format!("post effect {} isn't a {name} effect", effect.id));
+            }
+            None => return Err(format!("post effect {} does not exist", effect.id)),
+        }
+        self.set_params(queue, effect, &params.to_params())?;
+
+        if let BuiltinEffect::Lut(lut_params) = params {
+            let data = &self.list[effect.id as usize];
+            if !matches!(&data.lut, Some((lut, _)) if lut.is_same(&lut_params.lut)) {
+                let view = lut_params.lut.create_view(device, queue);
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.lut_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&view),
+                        },
+                    ],
+                    label: Some("post_lut_bind_group"),
+                });
+                self.list[effect.id as usize].lut = Some((lut_params.lut.clone(), bind_group));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_chain(&self) -> &[PostEffect] {
+        &self.chain
+    }
+    /// Effects that don't exist are left out.
+    pub fn set_chain(&mut self, chain: &[PostEffect]) {
+        let exists = |effect: &&PostEffect| (effect.id as usize) < self.list.len();
+        self.chain = chain.iter().filter(exists).copied().collect();
+    }
+    pub fn is_active(&self) -> bool {
+        !self.chain.is_empty()
+    }
+
+    /// Creates the textures the scene and the effects render into, if their size changed.
+    pub fn prepare(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if self.target_size == (width, height) && !self.targets.is_empty() {
+            return;
+        }
+        self.targets = (0..2)
+            .map(|_| self.create_target(device, width, height))
+            .collect();
+        self.target_size = (width, height);
+    }
+
+    fn create_target(&self, device: &wgpu::Device, width: u32, height: u32) -> Target {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Post Effect Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.scene_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+            ],
+            label: Some("post_scene_bind_group"),
+        });
+        Target { view, bind_group }
+    }
+
+    /// The texture the scene gets rendered into while effects are in use, call `prepare` first.
+    pub fn get_scene_view(&self) -> &wgpu::TextureView {
+        &self.targets[0].view
+    }
+
+    /// Runs the chain on the scene, the last effect renders into `output`.
+    pub fn apply(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        for (i, effect) in self.chain.iter().enumerate() {
+            let data = &self.list[effect.id as usize];
+            let source = &self.targets[i % 2];
+            let view = match i + 1 == self.chain.len() {
+                true => output,
+                false => &self.targets[(i + 1) % 2].view,
+            };
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Post Effect Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(&data.pipeline);
+            render_pass.set_bind_group(0, &source.bind_group, &[]);
+            render_pass.set_bind_group(1, &data.params_bind_group, &[]);
+            if let Some((_, lut_bind_group)) = &data.lut {
+                render_pass.set_bind_group(2, lut_bind_group, &[]);
+            }
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
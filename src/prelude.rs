@@ -1,14 +1,28 @@
+pub use crate::builtin_effect::{
+    BloomParams, BuiltinEffect, ColorFilter, ColorFilterParams, ColorblindMode, ColorblindParams,
+    CrtParams, Lut, LutParams, PixelateParams,
+};
+pub use crate::camera::Camera;
+pub use crate::create_atlas_textures;
 pub use crate::create_textures;
+pub use crate::dynamic_texture::DynamicTexture;
 pub use crate::engine::Engine;
 pub use crate::engine_builder::EngineBuilder;
 pub use crate::input::{ButtonEnum as Button, Input};
+pub use crate::layer::{SCREEN_LAYER, WORLD_LAYER};
+pub use crate::material::{Material, MATERIAL_PARAM_AMT};
 pub use crate::math::{rect32, rect32_vec, rect64, rect64_vec, Rect32, Rect64, Vec32, Vec64};
-pub use crate::minor_types::{Animation, Color, DrawParams, Manager};
+pub use crate::minor_types::{Animation, Color, DrawParams, Manager, ShapeParams};
+pub use crate::post_effect::{PostEffect, POST_EFFECT_PARAM_AMT};
+pub use crate::render_target::RenderTarget;
 pub use crate::sound::{Sound, SoundFile};
-pub use crate::texture::Texture;
+pub use crate::text::{Font, TextAlign, TextParams};
+pub use crate::texture::{AddressMode, FilterMode, Texture, TextureError, TextureParams};
+pub use crate::texture_registry::TextureInfo;
 pub use crate::ui::UserUi;
 
 pub use cgmath::{vec2, InnerSpace};
+pub use image::{DynamicImage, RgbaImage};
 pub use pollster::block_on;
 pub use rodio::source::{Buffered, Source};
 pub use winit::event_loop::EventLoop;
@@ -0,0 +1,38 @@
+use crate::math::Vec32;
+use crate::texture::Texture;
+
+/// A texture that sprites can be drawn into with a camera of its own, see `Engine::set_render_target`.
+/// It keeps its pixels until sprites get drawn into it again, so it can also cache things that rarely change.
+#[derive(Clone)]
+pub struct RenderTarget {
+    texture: Texture,
+}
+impl RenderTarget {
+    pub(crate) fn new(texture: Texture) -> Self {
+        Self { texture }
+    }
+
+    pub(crate) fn get_id(&self) -> u32 {
+        self.texture.index
+    }
+
+    /// The texture with what was drawn into the target, it can be drawn like any other texture.
+    pub fn get_texture(&self) -> &Texture {
+        &self.texture
+    }
+    /// Returns the size in pixels.
+    pub fn get_size(&self) -> Vec32 {
+        self.texture.get_size()
+    }
+}
+
+/// What the layer of a render target draws into.
+pub(crate) struct LayerTarget {
+    pub texture: Texture,
+    pub clear_color: wgpu::Color,
+}
+impl LayerTarget {
+    pub fn get_id(&self) -> u32 {
+        self.texture.index
+    }
+}
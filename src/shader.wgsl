@@ -1,16 +1,10 @@
 
 struct CameraUniform {
-    pos: vec2<f32>,
-};
-struct WindowSizeUniform {
-    x: f32,
-    y: f32,
+    view_proj: mat4x4<f32>,
 };
 
 @group(1) @binding(0) 
 var<uniform> camera: CameraUniform;
-@group(2) @binding(0) 
-var<uniform> win_size: WindowSizeUniform;
 
 struct VertexInput {
     @location(0) pos: vec2<f32>,
@@ -28,12 +22,18 @@ struct InstanceInput {
     @location(6) vec2_1: vec2<f32>,
     @location(7) vec2_2: vec2<f32>,
     @location(8) index: u32,
+    @location(9) color: vec4<f32>,
+    @location(10) kind: u32,
+    @location(11) sampler_index: u32,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
-    @location(1) index: u32
+    @location(1) index: u32,
+    @location(2) color: vec4<f32>,
+    @location(3) kind: u32,
+    @location(4) sampler_index: u32,
 };
 
 @vertex
@@ -45,6 +45,9 @@ fn vs_main(
 
     var out: VertexOutput;
     out.index = instance.index;
+    out.color = instance.color;
+    out.kind = instance.kind;
+    out.sampler_index = instance.sampler_index;
 
     if vertex.pos.x == 0. && vertex.pos.y == -2. {
         out.tex_coords = tex_coords.vec2_0;
@@ -56,29 +59,57 @@ fn vs_main(
         out.tex_coords = tex_coords.vec2_3;
     } 
 
-    var instance_mat = mat4x4<f32>(
+    let instance_mat = mat4x4<f32>(
         vec4<f32>(instance.vec2_0.x , instance.vec2_0.y, 0., 0.),
         vec4<f32>(instance.vec2_1.x, instance.vec2_1.y, 0., 0.),
         vec4<f32>(0., 0., 1., 0.),
         vec4<f32>(instance.vec2_2.x, instance.vec2_2.y, 0., 1.),
     );  
-    
-    instance_mat.w.x = instance_mat.w.x * win_size.x * 2. - 1.;
-    instance_mat.w.y = instance_mat.w.y * win_size.y * -2. + 1.;
 
-    let updated_model = instance_mat * vec4<f32>(vertex.pos.x * win_size.x, vertex.pos.y * win_size.y, 0., 1.);
-    // let updated_model = vec4<f32>(updated_pos.x + camera.pos.x, updated_pos.y + camera.pos.y, updated_pos.z, updated_pos.w);
-    out.clip_position = updated_model;
+    // the quad goes from (0, 0) to (2, -2), turn it into a unit square with the y-axis pointing down like the world
+    let world_pos = instance_mat * vec4<f32>(vertex.pos.x * 0.5, vertex.pos.y * -0.5, 0., 1.);
+    out.clip_position = camera.view_proj * world_pos;
 
     return out;
 }
 
-@group(0)@binding(0)
-var sam: sampler;
-@group(0) @binding(1)
-var tex_array: binding_array<texture_2d<f32>>;
+// the samplers at binding 0, the textures at binding 1 and sample_texture get added from texture_array.wgsl or texture_single.wgsl,
+// depending on whether the GPU supports binding arrays
+
+// has to match InstanceKind in vert_buffers.rs
+const KIND_RECT: u32 = 1u;
+const KIND_ELLIPSE: u32 = 2u;
+const KIND_TRIANGLE: u32 = 3u;
+
+// how much of the pixel lies inside of dist <= 1, for anti-aliased edges
+fn coverage(dist: f32, dist_width: f32) -> f32 {
+    return clamp((1. - dist) / max(dist_width, 0.00001) + 0.5, 0., 1.);
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(tex_array[in.index], sam, in.tex_coords);
+    // sampling and derivatives need uniform control flow, so they happen before branching on the kind
+    let tex_color = sample_texture(in.index, in.sampler_index, in.tex_coords);
+    // shapes use the default texture coordinates, which go from (0, 0) to (1, 1) over the quad
+    let ellipse_dist = length(in.tex_coords - vec2<f32>(0.5, 0.5)) * 2.;
+    let ellipse_width = fwidth(ellipse_dist);
+
+    switch in.kind {
+        case KIND_RECT: {
+            return in.color;
+        }
+        case KIND_ELLIPSE: {
+            return vec4<f32>(in.color.rgb, in.color.a * coverage(ellipse_dist, ellipse_width));
+        }
+        case KIND_TRIANGLE: {
+            // not anti-aliased, so triangles that share an edge don't leave a seam
+            if in.tex_coords.x + in.tex_coords.y > 1. {
+                discard;
+            }
+            return in.color;
+        }
+        default: {
+            return tex_color * in.color;
+        }
+    }
 }
\ No newline at end of file
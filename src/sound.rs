@@ -5,8 +5,8 @@ pub type SoundFile = Decoder<BufReader<File>>;
 
 pub struct Sound {
     #[allow(dead_code)] // stream is unused but it has to stay in memory
-    stream: rodio::OutputStream,
-    stream_handle: OutputStreamHandle,
+    stream: Option<rodio::OutputStream>,
+    stream_handle: Option<OutputStreamHandle>,
     use_sound: bool,
 }
 impl Sound {
@@ -14,25 +14,33 @@ impl Sound {
         let (stream, stream_handle) =
             rodio::OutputStream::try_default().expect("can't find output device");
         Self {
-            stream,
-            stream_handle,
+            stream: Some(stream),
+            stream_handle: Some(stream_handle),
             use_sound,
         }
     }
+    /// Sound without an output device, used when running headless.
+    pub(crate) fn new_muted() -> Self {
+        Self {
+            stream: None,
+            stream_handle: None,
+            use_sound: false,
+        }
+    }
 
     pub fn play_sound<S>(&self, source: S) -> Result<(), rodio::PlayError>
     where
         S: Source<Item = f32> + Send + 'static,
     {
-        if self.use_sound {
-            self.stream_handle.play_raw(source)?;
+        if let (true, Some(stream_handle)) = (self.use_sound, &self.stream_handle) {
+            stream_handle.play_raw(source)?;
         }
         Ok(())
     }
 
     pub fn use_sound(&mut self, use_sound: bool) {
         self.use_sound = use_sound;
-        if !use_sound {
+        if !use_sound && self.stream_handle.is_some() {
             *self = Sound::new(false);
         }
     }
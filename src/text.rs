@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+
+use ab_glyph::{Font as _, FontArc, GlyphId, ScaleFont};
+use cgmath::vec2;
+
+use crate::math::{rect32, Rect32, Vec32};
+use crate::minor_types::Color;
+use crate::texture::{Texture, TextureParams};
+use crate::texture_registry::TextureIndex;
+use crate::vert_buffers::TexCoords;
+
+const ATLAS_SIZE: u32 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TextParams {
+    /// Height of the font in world units
+    pub size: f32,
+    pub color: Color,
+    /// Whether the position of the text is the left edge, the center or the right edge of every line
+    pub align: TextAlign,
+    /// Lines that are wider get wrapped between words
+    pub max_width: Option<f32>,
+    pub z: f32,
+}
+impl TextParams {
+    pub fn from_size(size: f32) -> Self {
+        TextParams {
+            size,
+            ..Default::default()
+        }
+    }
+}
+impl Default for TextParams {
+    fn default() -> Self {
+        Self {
+            size: 32.,
+            color: Color::WHITE,
+            align: TextAlign::Left,
+            max_width: None,
+            z: 0.,
+        }
+    }
+}
+
+/// A TrueType/OpenType font or a bitmap font, created with `Engine::create_font` or `Engine::create_bitmap_font`.
+#[derive(Debug, Clone)]
+pub struct Font {
+    kind: FontKind,
+}
+
+#[derive(Debug, Clone)]
+enum FontKind {
+    TrueType {
+        font: FontArc,
+        id: u32,
+    },
+    Bitmap {
+        // keeps the index of the texture from being reused while the font exists
+        texture: Texture,
+        glyph_size: Vec32,
+        columns: u32,
+        chars: Vec<char>,
+    },
+}
+
+/// A glyph ready to be drawn, in the glyph atlas or the texture of a bitmap font.
+pub(crate) struct GlyphQuad {
+    pub rect: Rect32,
+    pub tex_coords: TexCoords,
+}
+
+impl Font {
+    pub(crate) fn from_bytes(bytes: &[u8], id: u32) -> Result<Self, String> {
+        let font = FontArc::try_from_vec(bytes.to_vec()).map_err(|e| e.to_string())?;
+        Ok(Self {
+            kind: FontKind::TrueType { font, id },
+        })
+    }
+
+    pub(crate) fn from_bitmap(texture: &Texture, glyph_size: Vec32, chars: &str) -> Self {
+        let region = texture.get_region();
+        Self {
+            kind: FontKind::Bitmap {
+                texture: texture.clone(),
+                glyph_size,
+                columns: ((region.w / glyph_size.x) as u32).max(1),
+                chars: chars.chars().collect(),
+            },
+        }
+    }
+
+    /// The texture of a bitmap font.
+    pub(crate) fn get_texture(&self) -> Option<&Texture> {
+        match &self.kind {
+            FontKind::TrueType { .. } => None,
+            FontKind::Bitmap { texture, .. } => Some(texture),
+        }
+    }
+
+    fn get_line_height(&self, size: f32) -> f32 {
+        match &self.kind {
+            FontKind::TrueType { font, .. } => {
+                let font = font.as_scaled(size);
+                font.height() + font.line_gap()
+            }
+            FontKind::Bitmap { .. } => size,
+        }
+    }
+
+    fn get_line_width(&self, line: &str, size: f32) -> f32 {
+        match &self.kind {
+            FontKind::TrueType { font, .. } => {
+                let font = font.as_scaled(size);
+                let mut width = 0.;
+                let mut prev: Option<GlyphId> = None;
+                for c in line.chars() {
+                    let id = font.glyph_id(c);
+                    if let Some(prev) = prev {
+                        width += font.kern(prev, id);
+                    }
+                    width += font.h_advance(id);
+                    prev = Some(id);
+                }
+                width
+            }
+            FontKind::Bitmap { glyph_size, .. } => {
+                line.chars().count() as f32 * glyph_size.x * size / glyph_size.y
+            }
+        }
+    }
+
+    fn wrap(&self, text: &str, params: &TextParams) -> Vec<String> {
+        let mut lines = vec![];
+        for paragraph in text.split('\n') {
+            let max_width = match params.max_width {
+                Some(max_width) => max_width,
+                None => {
+                    lines.push(paragraph.to_string());
+                    continue;
+                }
+            };
+
+            let mut line = String::new();
+            for word in paragraph.split_inclusive(' ') {
+                let candidate = format!("{line}{}", word.trim_end_matches(' '));
+                if !line.is_empty() && self.get_line_width(&candidate, params.size) > max_width {
+                    lines.push(line.trim_end_matches(' ').to_string());
+                    line.clear();
+                }
+                line.push_str(word);
+            }
+            lines.push(line.trim_end_matches(' ').to_string());
+        }
+        lines
+    }
+
+    pub(crate) fn measure(&self, text: &str, params: &TextParams) -> Vec32 {
+        let lines = self.wrap(text, params);
+        let width = lines
+            .iter()
+            .map(|line| self.get_line_width(line, params.size))
+            .fold(0., f32::max);
+        vec2(
+            width,
+            lines.len() as f32 * self.get_line_height(params.size),
+        )
+    }
+
+    pub(crate) fn layout(
+        &self,
+        text: &str,
+        pos: Vec32,
+        params: &TextParams,
+        cache: &mut GlyphCache,
+        queue: &wgpu::Queue,
+    ) -> Vec<GlyphQuad> {
+        let line_height = self.get_line_height(params.size);
+        let mut quads = vec![];
+        for (i, line) in self.wrap(text, params).iter().enumerate() {
+            let width = self.get_line_width(line, params.size);
+            let x = match params.align {
+                TextAlign::Left => pos.x,
+                TextAlign::Center => pos.x - width * 0.5,
+                TextAlign::Right => pos.x - width,
+            };
+            let line_pos = vec2(x, pos.y + i as f32 * line_height);
+            self.layout_line(line, line_pos, params.size, cache, queue, &mut quads);
+        }
+        quads
+    }
+
+    fn layout_line(
+        &self,
+        line: &str,
+        pos: Vec32,
+        size: f32,
+        cache: &mut GlyphCache,
+        queue: &wgpu::Queue,
+        quads: &mut Vec<GlyphQuad>,
+    ) {
+        match &self.kind {
+            FontKind::TrueType { font, id } => {
+                let scaled = font.as_scaled(size);
+                let baseline = pos.y + scaled.ascent();
+                let mut x = pos.x;
+                let mut prev: Option<GlyphId> = None;
+                for c in line.chars() {
+                    let glyph_id = scaled.glyph_id(c);
+                    if let Some(prev) = prev {
+                        x += scaled.kern(prev, glyph_id);
+                    }
+                    if let Some(glyph) = cache.get_glyph(queue, font, *id, glyph_id, size) {
+                        let (w, h) = (glyph.source.w, glyph.source.h);
+                        quads.push(GlyphQuad {
+                            rect: rect32(x + glyph.offset.x, baseline + glyph.offset.y, w, h),
+                            tex_coords: TexCoords::from_rect(glyph.source, cache.get_inv_size()),
+                        });
+                    }
+                    x += scaled.h_advance(glyph_id);
+                    prev = Some(glyph_id);
+                }
+            }
+            FontKind::Bitmap {
+                texture,
+                glyph_size,
+                columns,
+                chars,
+            } => {
+                let scale = size / glyph_size.y;
+                // the glyphs start at the top left of the region if the texture is part of an atlas
+                let region = texture.get_region();
+                let inv_size = vec2(texture.get_inv_width(), texture.get_inv_height());
+                for (i, c) in line.chars().enumerate() {
+                    let Some(index) = chars.iter().position(|&ch| ch == c) else {
+                        continue;
+                    };
+                    let (column, row) = (index as u32 % columns, index as u32 / columns);
+                    let source = rect32(
+                        region.x + column as f32 * glyph_size.x,
+                        region.y + row as f32 * glyph_size.y,
+                        glyph_size.x,
+                        glyph_size.y,
+                    );
+                    let x = pos.x + i as f32 * glyph_size.x * scale;
+                    quads.push(GlyphQuad {
+                        rect: rect32(x, pos.y, glyph_size.x * scale, size),
+                        tex_coords: TexCoords::from_rect(source, inv_size),
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CachedGlyph {
+    // where the glyph lies in the atlas
+    source: Rect32,
+    // from the pen position on the baseline to the top left of the glyph
+    offset: Vec32,
+}
+
+/// Rasterizes glyphs of TrueType fonts into one atlas texture on demand.
+pub(crate) struct GlyphCache {
+    texture: Texture,
+    // glyphs without an outline, like spaces, are None
+    glyphs: HashMap<(u32, u16, u32), Option<CachedGlyph>>,
+    cursor: (u32, u32),
+    row_height: u32,
+    full: bool,
+    fonts_created: u32,
+}
+impl GlyphCache {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, index: TextureIndex) -> Self {
+        let img = image::RgbaImage::new(ATLAS_SIZE, ATLAS_SIZE);
+        let texture = Texture::from_image(
+            device,
+            queue,
+            &image::DynamicImage::ImageRgba8(img),
+            index,
+            Some("Glyph Atlas"),
+            TextureParams::default(),
+        );
+
+        Self {
+            texture,
+            glyphs: HashMap::new(),
+            cursor: (0, 0),
+            row_height: 0,
+            full: false,
+            fonts_created: 0,
+        }
+    }
+
+    pub fn get_texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    fn get_inv_size(&self) -> Vec32 {
+        vec2(1. / ATLAS_SIZE as f32, 1. / ATLAS_SIZE as f32)
+    }
+
+    pub fn next_font_id(&mut self) -> u32 {
+        self.fonts_created += 1;
+        self.fonts_created - 1
+    }
+
+    fn get_glyph(
+        &mut self,
+        queue: &wgpu::Queue,
+        font: &FontArc,
+        font_id: u32,
+        glyph_id: GlyphId,
+        size: f32,
+    ) -> Option<CachedGlyph> {
+        let key = (font_id, glyph_id.0, size.to_bits());
+        if let Some(glyph) = self.glyphs.get(&key) {
+            return *glyph;
+        }
+
+        let glyph = match font.outline_glyph(glyph_id.with_scale(size)) {
+            Some(outlined) => {
+                let bounds = outlined.px_bounds();
+                let (w, h) = (bounds.width() as u32, bounds.height() as u32);
+                // Glyphs can't be added while the atlas is full, it gets cleared after this frame
+                let (x, y) = self.allocate(w, h)?;
+
+                // Write a transparent border too, so linear filtering doesn't pick up old glyphs
+                let mut pixels = vec![0; ((w + 2) * (h + 2) * 4) as usize];
+                for pixel in pixels.chunks_mut(4) {
+                    pixel[..3].copy_from_slice(&[255; 3]);
+                }
+                outlined.draw(|gx, gy, coverage| {
+                    let i = (((gy + 1) * (w + 2) + gx + 1) * 4 + 3) as usize;
+                    pixels[i] = (coverage.clamp(0., 1.) * 255.) as u8;
+                });
+                self.write(queue, &pixels, (x, y), (w + 2, h + 2));
+
+                Some(CachedGlyph {
+                    source: rect32((x + 1) as f32, (y + 1) as f32, w as f32, h as f32),
+                    offset: vec2(bounds.min.x, bounds.min.y),
+                })
+            }
+            None => None,
+        };
+
+        self.glyphs.insert(key, glyph);
+        glyph
+    }
+
+    // returns the top left of a free area of w x h plus a pixel of border on every side
+    fn allocate(&mut self, w: u32, h: u32) -> Option<(u32, u32)> {
+        let (w, h) = (w + 2, h + 2);
+        if self.cursor.0 + w > ATLAS_SIZE {
+            self.cursor = (0, self.cursor.1 + self.row_height);
+            self.row_height = 0;
+        }
+        if w > ATLAS_SIZE || self.cursor.1 + h > ATLAS_SIZE {
+            self.full = true;
+            return None;
+        }
+
+        let pos = self.cursor;
+        self.cursor.0 += w;
+        self.row_height = self.row_height.max(h);
+        Some(pos)
+    }
+
+    fn write(&self, queue: &wgpu::Queue, pixels: &[u8], pos: (u32, u32), size: (u32, u32)) {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: pos.0,
+                    y: pos.1,
+                    z: 0,
+                },
+            },
+            pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size.0),
+                rows_per_image: Some(size.1),
+            },
+            wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Forgets all glyphs once the atlas ran full, they get rasterized again when they're drawn.
+    pub fn clear_if_full(&mut self) {
+        if self.full {
+            self.glyphs.clear();
+            self.cursor = (0, 0);
+            self.row_height = 0;
+            self.full = false;
+        }
+    }
+}
@@ -1,14 +1,35 @@
+use std::sync::{Arc, Weak};
+
 use cgmath::vec2;
 use image::GenericImageView;
 use wgpu::Device;
 
+use crate::math::{rect32, Rect32, Vec32};
+use crate::texture_registry::{TextureIndex, TextureInfo};
+
+/// Clones share the same GPU texture, textures of an atlas all share the texture of their atlas page.
+/// The GPU texture gets freed before the next frame once every clone is dropped, or right away by
+/// `Engine::unload_texture`. The engine doesn't keep textures alive by using them, only fonts and render
+/// targets hold on to theirs.
+#[derive(Clone)]
 pub struct Texture {
-    #[allow(dead_code)]
-    pub(crate) texture: wgpu::Texture,
-    pub(crate) view: wgpu::TextureView,
-    pub(crate) sampler: wgpu::Sampler,
+    pub(crate) texture: Arc<wgpu::Texture>,
+    pub(crate) view: Arc<wgpu::TextureView>,
+    // index of the sampler in the samplers shared by all textures
+    pub(crate) sampler: u32,
     pub(crate) index: u32,
+    handle: Arc<TextureIndex>,
     inv_size: cgmath::Vector2<f32>,
+    // the part of the GPU texture in pixels this texture covers, None if it covers all of it
+    region: Option<Rect32>,
+}
+impl std::fmt::Debug for Texture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Texture")
+            .field("index", &self.index)
+            .field("size", &self.get_size())
+            .finish()
+    }
 }
 impl Texture {
     pub(crate) fn get_inv_width(&self) -> f32 {
@@ -18,57 +39,90 @@ impl Texture {
         self.inv_size.y
     }
 
-    pub(crate) fn from_bytes(
+    /// Returns the size in pixels, for textures of an atlas the size of their region.
+    pub fn get_size(&self) -> Vec32 {
+        let region = self.get_region();
+        vec2(region.w, region.h)
+    }
+    /// Returns the part of the GPU texture in pixels this texture covers.
+    pub(crate) fn get_region(&self) -> Rect32 {
+        match self.region {
+            Some(region) => region,
+            None => rect32(0., 0., 1. / self.inv_size.x, 1. / self.inv_size.y),
+        }
+    }
+    /// Returns true once `Engine::unload_texture` was called on this texture or a clone of it.
+    pub fn is_unloaded(&self) -> bool {
+        self.handle.is_unloaded()
+    }
+    /// Frees the GPU texture, drawing it afterwards does nothing.
+    pub(crate) fn unload(&self, queue: &wgpu::Queue) {
+        self.handle.set_unloaded();
+        // uploads to the texture that are still pending would fail once it's destroyed
+        queue.submit([]);
+        self.texture.destroy();
+    }
+    /// A reference that doesn't keep the GPU texture alive.
+    pub(crate) fn downgrade(&self) -> WeakTexture {
+        WeakTexture {
+            texture: Arc::downgrade(&self.texture),
+            view: Arc::downgrade(&self.view),
+            sampler: self.sampler,
+            index: self.index,
+            handle: Arc::downgrade(&self.handle),
+            inv_size: self.inv_size,
+            region: self.region,
+        }
+    }
+    pub(crate) fn has_region(&self) -> bool {
+        self.region.is_some()
+    }
+    pub(crate) fn with_region(&self, region: Rect32) -> Self {
+        Self {
+            region: Some(region),
+            ..self.clone()
+        }
+    }
+
+    /// A single white pixel, bound when no textures are in use so shapes can still be drawn.
+    pub(crate) fn white_pixel(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        index: u32,
-        bytes: &[u8],
-        use_near_filter_mode: bool,
-    ) -> Result<Self, String> {
-        let img = match image::load_from_memory(bytes) {
-            Ok(img) => img,
-            Err(_) => return Err("could not load image from memory".to_string()),
-        };
-        Ok(Self::from_image(
+        index: TextureIndex,
+    ) -> Self {
+        let img = image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+        Self::from_image(
             device,
             queue,
-            &img,
+            &image::DynamicImage::ImageRgba8(img),
             index,
-            None,
-            use_near_filter_mode,
-        ))
+            Some("White Pixel Texture"),
+            TextureParams::from_filter_mode(FilterMode::Nearest),
+        )
     }
 
-    /*pub(crate) fn from_path(
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        index: u32,
-        label: &str,
-    ) -> Result<Self> {
-        let img = image::open(label)?;
-        Ok(Self::from_image(device, queue, &img, index, Some(label)))
-    }*/
-
     pub(crate) fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
-        index: u32,
+        index: TextureIndex,
         label: Option<&str>,
-        use_near_filter_mode: bool,
+        params: TextureParams,
     ) -> Self {
-        let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
-
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
+        let levels = match params.mipmaps {
+            true => create_mipmaps(img.to_rgba8()),
+            false => vec![img.to_rgba8()],
         };
+
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
-            size,
-            mip_level_count: 1,
+            size: wgpu::Extent3d {
+                width: dimensions.0,
+                height: dimensions.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: levels.len() as u32,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -76,46 +130,318 @@ impl Texture {
             view_formats: &[],
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
+        for (mip_level, level) in levels.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: mip_level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                level,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * level.width()),
+                    rows_per_image: Some(level.height()),
+                },
+                wgpu::Extent3d {
+                    width: level.width(),
+                    height: level.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let bytes = levels.iter().map(|level| level.len() as u64).sum();
+        Self::from_wgpu(texture, index, label, params, bytes)
+    }
+
+    /// A texture that sprites can be drawn into and that can be drawn itself, in the format of the frame.
+    pub(crate) fn render_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        index: TextureIndex,
+        params: TextureParams,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Render Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
             },
-            size,
-        );
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let bytes = 4 * width as u64 * height as u64;
+        Self::from_wgpu(texture, index, Some("Render Target"), params, bytes)
+    }
 
-        let filter_mode = if use_near_filter_mode {
-            wgpu::FilterMode::Nearest
-        } else {
-            wgpu::FilterMode::Linear
-        };
+    fn from_wgpu(
+        texture: wgpu::Texture,
+        index: TextureIndex,
+        label: Option<&str>,
+        params: TextureParams,
+        bytes: u64,
+    ) -> Self {
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: filter_mode,
-            min_filter: filter_mode,
-            mipmap_filter: filter_mode,
-            ..Default::default()
-        });
 
         let inv_size = vec2(1. / texture.width() as f32, 1. / texture.height() as f32);
 
+        index.indices().set_live(TextureInfo {
+            index: index.get(),
+            label: label.unwrap_or("Texture").to_string(),
+            width: texture.width(),
+            height: texture.height(),
+            bytes,
+        });
+
         Self {
-            texture,
-            view,
-            sampler,
-            index,
+            texture: Arc::new(texture),
+            view: Arc::new(view),
+            sampler: params.get_sampler_index(),
+            index: index.get(),
+            handle: Arc::new(index),
             inv_size,
+            region: None,
+        }
+    }
+}
+
+/// A texture that's only drawable while a clone of it exists somewhere else.
+pub(crate) struct WeakTexture {
+    texture: Weak<wgpu::Texture>,
+    view: Weak<wgpu::TextureView>,
+    sampler: u32,
+    index: u32,
+    handle: Weak<TextureIndex>,
+    inv_size: cgmath::Vector2<f32>,
+    region: Option<Rect32>,
+}
+impl WeakTexture {
+    pub fn upgrade(&self) -> Option<Texture> {
+        Some(Texture {
+            texture: self.texture.upgrade()?,
+            view: self.view.upgrade()?,
+            sampler: self.sampler,
+            index: self.index,
+            handle: self.handle.upgrade()?,
+            inv_size: self.inv_size,
+            region: self.region,
+        })
+    }
+    /// Whether a clone of the texture still exists.
+    pub fn is_alive(&self) -> bool {
+        self.handle.strong_count() > 0
+    }
+    /// Whether `tex` shares its GPU texture with this one.
+    pub fn is(&self, tex: &Texture) -> bool {
+        self.texture.as_ptr() == Arc::as_ptr(&tex.texture)
+    }
+}
+
+// Halves the size until it's 1x1, every level is filtered from the one before. Filtering happens on
+// linear premultiplied colors like on the GPU, so smaller levels don't get darker or bleed transparent pixels.
+fn create_mipmaps(img: image::RgbaImage) -> Vec<image::RgbaImage> {
+    let mut linear = image::Rgba32FImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, a] = img.get_pixel(x, y).0;
+        let a = a as f32 / 255.;
+        image::Rgba([
+            srgb_to_linear(r) * a,
+            srgb_to_linear(g) * a,
+            srgb_to_linear(b) * a,
+            a,
+        ])
+    });
+
+    let mut levels = vec![img];
+    while linear.width() > 1 || linear.height() > 1 {
+        let (width, height) = ((linear.width() / 2).max(1), (linear.height() / 2).max(1));
+        linear = image::imageops::resize(
+            &linear,
+            width,
+            height,
+            image::imageops::FilterType::Triangle,
+        );
+        let level = image::RgbaImage::from_fn(width, height, |x, y| {
+            let [r, g, b, a] = linear.get_pixel(x, y).0;
+            let unpremultiply = |c: f32| if a > 0. { linear_to_srgb(c / a) } else { 0 };
+            image::Rgba([
+                unpremultiply(r),
+                unpremultiply(g),
+                unpremultiply(b),
+                (a * 255.).round() as u8,
+            ])
+        });
+        levels.push(level);
+    }
+    levels
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0., 1.);
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    };
+    (c * 255.).round() as u8
+}
+
+/// Why a texture couldn't be created.
+#[derive(Debug)]
+pub enum TextureError {
+    /// The image couldn't be read or decoded
+    Image(image::ImageError),
+    /// Raw pixel data that doesn't have exactly 4 bytes for every pixel
+    DataSize { width: u32, height: u32, len: usize },
+    /// An empty image, or one that's bigger than `max` pixels in a direction
+    Dimensions { width: u32, height: u32, max: u32 },
+    /// A color grading strip that isn't n*n pixels wide and n pixels high, with n between 2 and 256
+    LutSize { width: u32, height: u32 },
+}
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::Image(e) => write!(f, "could not load image: {e}"),
+            TextureError::DataSize { width, height, len } => write!(
+                f,
+                "{len} bytes of pixel data don't match an image of {width}x{height} pixels"
+            ),
+            TextureError::Dimensions { width, height, max } => write!(
+                f,
+                "an image of {width}x{height} pixels doesn't fit into a texture of at most {max}x{max} pixels"
+            ),
+            TextureError::LutSize { width, height } => write!(
+                f,
+                "a LUT of {width}x{height} pixels isn't a strip of n*n by n pixels with n between 2 and 256"
+            ),
+        }
+    }
+}
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Image(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<image::ImageError> for TextureError {
+    fn from(e: image::ImageError) -> Self {
+        TextureError::Image(e)
+    }
+}
+
+/// How a texture gets filtered when it's scaled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterMode {
+    /// Keeps hard pixel edges, for pixel art
+    Nearest,
+    #[default]
+    Linear,
+}
+impl FilterMode {
+    fn to_wgpu(self) -> wgpu::FilterMode {
+        match self {
+            FilterMode::Nearest => wgpu::FilterMode::Nearest,
+            FilterMode::Linear => wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+const FILTER_MODES: [FilterMode; 2] = [FilterMode::Nearest, FilterMode::Linear];
+const FILTER_MODE_AMT: u32 = FILTER_MODES.len() as u32;
+
+/// What a texture shows outside of its edges, when `DrawParams::source` reaches past them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressMode {
+    /// Repeats the edge pixels
+    #[default]
+    Clamp,
+    /// Tiles the texture, for scrolling backgrounds
+    Repeat,
+    /// Tiles the texture, flipping every other tile
+    Mirror,
+}
+impl AddressMode {
+    fn to_wgpu(self) -> wgpu::AddressMode {
+        match self {
+            AddressMode::Clamp => wgpu::AddressMode::ClampToEdge,
+            AddressMode::Repeat => wgpu::AddressMode::Repeat,
+            AddressMode::Mirror => wgpu::AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+const ADDRESS_MODES: [AddressMode; 3] =
+    [AddressMode::Clamp, AddressMode::Repeat, AddressMode::Mirror];
+
+// Textures share a sampler for every combination of filter and address mode, so binding arrays only need a few
+pub(crate) const SAMPLER_AMT: u32 = ADDRESS_MODES.len() as u32 * FILTER_MODE_AMT;
+
+/// Creates the samplers shared by all textures, at the index `TextureParams::get_sampler_index` returns.
+pub(crate) fn create_samplers(device: &Device) -> Vec<wgpu::Sampler> {
+    let mut samplers = vec![];
+    for address_mode in ADDRESS_MODES {
+        for filter_mode in FILTER_MODES {
+            let (address_mode, filter_mode) = (address_mode.to_wgpu(), filter_mode.to_wgpu());
+            samplers.push(device.create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: address_mode,
+                address_mode_v: address_mode,
+                address_mode_w: address_mode,
+                mag_filter: filter_mode,
+                min_filter: filter_mode,
+                mipmap_filter: filter_mode,
+                ..Default::default()
+            }));
+        }
+    }
+    samplers
+}
+
+/// How a texture gets sampled. Textures of an atlas share the params of their atlas, and since the
+/// address mode applies to the whole atlas, repeating only works for textures that aren't part of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TextureParams {
+    pub filter_mode: FilterMode,
+    pub address_mode: AddressMode,
+    /// Stores smaller versions of the texture to draw it without shimmering when it's scaled down,
+    /// at the cost of a third more memory. The filter mode also applies between the versions.
+    /// Dynamic textures, render targets and textures of an atlas never have mipmaps.
+    pub mipmaps: bool,
+}
+impl TextureParams {
+    fn get_sampler_index(&self) -> u32 {
+        self.address_mode as u32 * FILTER_MODE_AMT + self.filter_mode as u32
+    }
+
+    pub fn from_filter_mode(filter_mode: FilterMode) -> Self {
+        Self {
+            filter_mode,
+            ..Default::default()
+        }
+    }
+    pub fn from_address_mode(address_mode: AddressMode) -> Self {
+        Self {
+            address_mode,
+            ..Default::default()
         }
     }
 }
@@ -131,7 +457,58 @@ macro_rules! create_textures {
     };
 }
 
-pub fn create_bind_group_layout(device: &Device, tex_amt: u32) -> wgpu::BindGroupLayout {
+/// Like `create_textures!`, but packs all images into as few GPU textures as possible.
+#[macro_export]
+macro_rules! create_atlas_textures {
+    ($engine: expr, $textures: expr, $($name: expr)*) => {
+        let images: Vec<&[u8]> = vec![$(include_bytes!($name) as &[u8]),*];
+        $textures.extend($engine.create_texture_atlas(&images).unwrap());
+        $engine.use_textures(&$textures);
+    };
+}
+
+/// How textures get bound, binding arrays of textures aren't supported by every GPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TextureBinding {
+    /// All textures in one binding array, every instance picks its texture by index
+    Array,
+    /// A bind group per texture, instances get drawn with one draw call per texture they use in a row
+    Single,
+}
+impl TextureBinding {
+    pub fn array_features() -> wgpu::Features {
+        wgpu::Features::TEXTURE_BINDING_ARRAY
+            | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+    }
+    pub fn from_features(features: wgpu::Features) -> Self {
+        if features.contains(Self::array_features()) {
+            TextureBinding::Array
+        } else {
+            TextureBinding::Single
+        }
+    }
+    /// Declares the textures and the sample_texture function used by shader.wgsl.
+    pub fn get_shader_source(&self) -> &'static str {
+        match self {
+            TextureBinding::Array => include_str!("texture_array.wgsl"),
+            TextureBinding::Single => include_str!("texture_single.wgsl"),
+        }
+    }
+}
+
+pub fn create_bind_group_layout(
+    device: &Device,
+    binding: TextureBinding,
+    tex_amt: u32,
+) -> wgpu::BindGroupLayout {
+    // With binding arrays all samplers are bound and every instance picks the one of its texture
+    let (sampler_count, count) = match binding {
+        TextureBinding::Array => (
+            std::num::NonZeroU32::new(SAMPLER_AMT),
+            std::num::NonZeroU32::new(tex_amt),
+        ),
+        TextureBinding::Single => (None, None),
+    };
     device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[
             wgpu::BindGroupLayoutEntry {
@@ -140,7 +517,7 @@ pub fn create_bind_group_layout(device: &Device, tex_amt: u32) -> wgpu::BindGrou
                 // This should match the filterable field of the
                 // corresponding Texture entry above.
                 ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                count: None,
+                count: sampler_count,
             },
             wgpu::BindGroupLayoutEntry {
                 binding: 1,
@@ -150,54 +527,62 @@ pub fn create_bind_group_layout(device: &Device, tex_amt: u32) -> wgpu::BindGrou
                     view_dimension: wgpu::TextureViewDimension::D2,
                     sample_type: wgpu::TextureSampleType::Float { filterable: true },
                 },
-                count: std::num::NonZeroU32::new(tex_amt),
+                count,
             },
         ],
         label: Some("texture_bind_group_layout"),
     })
 }
 
-pub fn create_bind_group(
+/// Returns one bind group with all textures for `TextureBinding::Array`, or one bind group per texture.
+/// `samplers` are the ones of `create_samplers`.
+pub fn create_bind_groups(
     device: &Device,
     tex_bind_group_layout: &wgpu::BindGroupLayout,
-    textures: &Vec<Texture>,
-) -> wgpu::BindGroup {
-    let mut views = vec![];
-    for tex in textures {
-        views.push(&tex.view);
+    binding: TextureBinding,
+    textures: &[&Texture],
+    samplers: &[wgpu::Sampler],
+) -> Vec<wgpu::BindGroup> {
+    match binding {
+        TextureBinding::Array => {
+            let samplers: Vec<_> = samplers.iter().collect();
+            let samplers = wgpu::BindingResource::SamplerArray(&samplers);
+            let views: Vec<_> = textures.iter().map(|tex| tex.view.as_ref()).collect();
+            let views = wgpu::BindingResource::TextureViewArray(&views);
+            vec![create_bind_group(
+                device,
+                tex_bind_group_layout,
+                samplers,
+                views,
+            )]
+        }
+        TextureBinding::Single => textures
+            .iter()
+            .map(|tex| {
+                let sampler = wgpu::BindingResource::Sampler(&samplers[tex.sampler as usize]);
+                let view = wgpu::BindingResource::TextureView(&tex.view);
+                create_bind_group(device, tex_bind_group_layout, sampler, view)
+            })
+            .collect(),
     }
-
-    device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: tex_bind_group_layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::Sampler(&textures[0].sampler),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::TextureViewArray(&views),
-            },
-        ],
-        label: Some("texture_bind_group"),
-    })
 }
 
-pub fn create_bind_group_single_tex(
+fn create_bind_group(
     device: &Device,
     tex_bind_group_layout: &wgpu::BindGroupLayout,
-    tex: &Texture,
+    samplers: wgpu::BindingResource,
+    views: wgpu::BindingResource,
 ) -> wgpu::BindGroup {
     device.create_bind_group(&wgpu::BindGroupDescriptor {
         layout: tex_bind_group_layout,
         entries: &[
             wgpu::BindGroupEntry {
                 binding: 0,
-                resource: wgpu::BindingResource::Sampler(&tex.sampler),
+                resource: samplers,
             },
             wgpu::BindGroupEntry {
                 binding: 1,
-                resource: wgpu::BindingResource::TextureViewArray(&vec![&tex.view]),
+                resource: views,
             },
         ],
         label: Some("texture_bind_group"),
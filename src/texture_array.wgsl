@@ -0,0 +1,10 @@
+// all textures in one binding array and the samplers shared by them in another,
+// every instance picks its texture and the sampler of the texture by index
+@group(0) @binding(0)
+var sam_array: binding_array<sampler>;
+@group(0) @binding(1)
+var tex_array: binding_array<texture_2d<f32>>;
+
+fn sample_texture(index: u32, sampler_index: u32, tex_coords: vec2<f32>) -> vec4<f32> {
+    return textureSample(tex_array[index], sam_array[sampler_index], tex_coords);
+}
@@ -0,0 +1,420 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use wgpu::{BindGroup, BindGroupLayout, Device, RenderPipeline};
+
+use crate::engine_builder::{create_render_pipeline, create_render_pipeline_layout, create_shader};
+use crate::texture::{self, Texture, TextureBinding, WeakTexture};
+
+// The textures of the engine itself come before the textures of the game
+pub(crate) const WHITE_PIXEL_INDEX: u32 = 0;
+pub(crate) const GLYPH_ATLAS_INDEX: u32 = 1;
+pub(crate) const ENGINE_TEXTURE_AMT: u32 = 2;
+
+// binding arrays grow in powers of two from here, so adding a few textures doesn't need a new pipeline every time
+const MIN_ARRAY_LEN: u32 = 16;
+
+/// A texture on the GPU, as listed in the engine UI.
+#[derive(Debug, Clone)]
+pub struct TextureInfo {
+    pub index: u32,
+    pub label: String,
+    pub width: u32,
+    pub height: u32,
+    pub bytes: u64,
+}
+
+#[derive(Default)]
+struct IndexState {
+    created: u32,
+    free: Vec<u32>,
+    live: BTreeMap<u32, TextureInfo>,
+}
+
+/// Hands out the indices of textures and keeps track of the textures alive on the GPU.
+/// An index only gets reused after every clone of its texture was dropped.
+#[derive(Clone)]
+pub(crate) struct TextureIndices(Arc<Mutex<IndexState>>);
+impl TextureIndices {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(IndexState {
+            created: ENGINE_TEXTURE_AMT,
+            ..Default::default()
+        })))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, IndexState> {
+        // the state stays consistent even if a thread panicked while holding the lock
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn next(&self) -> TextureIndex {
+        let mut state = self.lock();
+        let index = match state.free.pop() {
+            Some(index) => index,
+            None => {
+                state.created += 1;
+                state.created - 1
+            }
+        };
+        self.reserved(index)
+    }
+    /// For the textures of the engine, which have fixed indices.
+    pub fn reserved(&self, index: u32) -> TextureIndex {
+        TextureIndex {
+            index,
+            indices: self.clone(),
+            unloaded: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set_live(&self, info: TextureInfo) {
+        self.lock().live.insert(info.index, info);
+    }
+    pub fn get_live(&self) -> Vec<TextureInfo> {
+        self.lock().live.values().cloned().collect()
+    }
+}
+
+/// Shared by all clones of a texture, gives the index back once the last clone is dropped.
+pub(crate) struct TextureIndex {
+    index: u32,
+    indices: TextureIndices,
+    unloaded: AtomicBool,
+}
+impl TextureIndex {
+    pub fn get(&self) -> u32 {
+        self.index
+    }
+    pub fn indices(&self) -> &TextureIndices {
+        &self.indices
+    }
+    pub fn is_unloaded(&self) -> bool {
+        self.unloaded.load(Ordering::Relaxed)
+    }
+    pub fn set_unloaded(&self) {
+        self.unloaded.store(true, Ordering::Relaxed);
+        self.indices.lock().live.remove(&self.index);
+    }
+}
+impl Drop for TextureIndex {
+    fn drop(&mut self) {
+        let mut state = self.indices.lock();
+        state.live.remove(&self.index);
+        // The indices of the engine's own textures are never handed out by `next`
+        if self.index >= ENGINE_TEXTURE_AMT {
+            state.free.push(self.index);
+        }
+    }
+}
+
+/// The textures that can be drawn, every texture is bound at its index. Bind groups only get recreated
+/// before the next frame after textures were added or removed, and pipelines are kept for every layout.
+pub(crate) struct TextureRegistry {
+    binding: TextureBinding,
+    indices: TextureIndices,
+    // fills the slots of textures that aren't registered
+    white_pixel: Texture,
+    samplers: Vec<wgpu::Sampler>,
+    // registering a texture doesn't keep it alive, its slot gets emptied once the game dropped every clone
+    slots: Vec<Option<WeakTexture>>,
+    // the length of the binding array, always 1 without binding arrays
+    array_len: u32,
+    max_array_len: u32,
+    // a single bind group with all textures, or one per slot if binding arrays aren't supported
+    bind_groups: Vec<Option<BindGroup>>,
+    // with binding arrays, the bind group for drawing into a render target, without the target itself
+    target_bind_groups: HashMap<u32, BindGroup>,
+    outdated: bool,
+
+    shader: wgpu::ShaderModule,
+    cam_layout: BindGroupLayout,
+    format: wgpu::TextureFormat,
+    // bind group layout for every array_len used so far
+    layouts: HashMap<u32, BindGroupLayout>,
+    // pipeline for every array_len and material used so far, material 0 is the default fragment shader
+    pipelines: HashMap<(u32, u32), RenderPipeline>,
+}
+impl TextureRegistry {
+    pub fn new(
+        device: &Device,
+        queue: &wgpu::Queue,
+        binding: TextureBinding,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let max_array_len = device.limits().max_sampled_textures_per_shader_stage;
+
+        let indices = TextureIndices::new();
+        let white_pixel = Texture::white_pixel(device, queue, indices.reserved(WHITE_PIXEL_INDEX));
+        let mut registry = Self {
+            binding,
+            indices,
+            white_pixel: white_pixel.clone(),
+            samplers: texture::create_samplers(device),
+            slots: vec![],
+            array_len: 0,
+            max_array_len,
+            bind_groups: vec![],
+            target_bind_groups: HashMap::new(),
+            outdated: true,
+            shader: create_shader(device, binding),
+            cam_layout: crate::camera::create_bind_group_layout(device),
+            format,
+            layouts: HashMap::new(),
+            pipelines: HashMap::new(),
+        };
+        registry.add(&white_pixel);
+        registry.prepare(device);
+        registry
+    }
+
+    /// Registers `tex`, or the texture of its atlas, replacing the texture registered at the same index.
+    pub fn add(&mut self, tex: &Texture) {
+        if tex.is_unloaded() {
+            log::warn!(
+                "texture {} was unloaded and can't be used anymore",
+                tex.index
+            );
+            return;
+        }
+        let index = tex.index as usize;
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+        if let Some(registered) = &self.slots[index] {
+            if registered.is(tex) {
+                return;
+            }
+        }
+        self.slots[index] = Some(tex.downgrade());
+        self.set_outdated(index);
+    }
+
+    pub fn remove(&mut self, index: u32) {
+        if let Some(slot) = self.slots.get_mut(index as usize) {
+            *slot = None;
+            self.set_outdated(index as usize);
+        }
+    }
+
+    fn set_outdated(&mut self, index: usize) {
+        match self.binding {
+            TextureBinding::Array => self.outdated = true,
+            TextureBinding::Single => {
+                if let Some(bind_group) = self.bind_groups.get_mut(index) {
+                    *bind_group = None;
+                }
+            }
+        }
+    }
+
+    /// Creates the bind groups and the pipeline for the textures registered since the last call.
+    pub fn prepare(&mut self, device: &Device) {
+        // The bind groups are the last thing holding on to dropped textures, recreating them frees the memory
+        for index in 0..self.slots.len() {
+            if self.slots[index]
+                .as_ref()
+                .is_some_and(|tex| !tex.is_alive())
+            {
+                self.slots[index] = None;
+                self.set_outdated(index);
+            }
+        }
+
+        let array_len = match self.binding {
+            TextureBinding::Array => {
+                let needed = (self.slots.len() as u32).max(MIN_ARRAY_LEN);
+                if needed > self.max_array_len {
+                    log::warn!(
+                        "only {} textures can be bound, {needed} are registered",
+                        self.max_array_len
+                    );
+                }
+                needed.next_power_of_two().min(self.max_array_len)
+            }
+            TextureBinding::Single => 1,
+        };
+        if array_len != self.array_len {
+            self.array_len = array_len;
+            self.outdated = true;
+        }
+
+        let binding = self.binding;
+        let tex_layout = self
+            .layouts
+            .entry(array_len)
+            .or_insert_with(|| texture::create_bind_group_layout(device, binding, array_len));
+        if !self.pipelines.contains_key(&(array_len, 0)) {
+            let pipeline_layout =
+                create_render_pipeline_layout(device, &[tex_layout, &self.cam_layout]);
+            let pipeline = create_render_pipeline(
+                device,
+                &pipeline_layout,
+                &self.shader,
+                "fs_main",
+                self.format,
+            );
+            self.pipelines.insert((array_len, 0), pipeline);
+        }
+
+        match self.binding {
+            TextureBinding::Array if self.outdated => {
+                let registered = upgrade_all(&self.slots);
+                let mut textures = vec![&self.white_pixel; array_len as usize];
+                for (slot, tex) in textures.iter_mut().zip(&registered) {
+                    if let Some(tex) = tex {
+                        *slot = tex;
+                    }
+                }
+                self.bind_groups = texture::create_bind_groups(
+                    device,
+                    tex_layout,
+                    binding,
+                    &textures,
+                    &self.samplers,
+                )
+                .into_iter()
+                .map(Some)
+                .collect();
+                self.target_bind_groups.clear();
+            }
+            TextureBinding::Array => {}
+            TextureBinding::Single => {
+                self.bind_groups.resize_with(self.slots.len(), || None);
+                for (bind_group, tex) in self.bind_groups.iter_mut().zip(&self.slots) {
+                    let tex = tex.as_ref().and_then(WeakTexture::upgrade);
+                    if let (None, Some(tex)) = (&bind_group, &tex) {
+                        let mut created = texture::create_bind_groups(
+                            device,
+                            tex_layout,
+                            binding,
+                            &[tex],
+                            &self.samplers,
+                        );
+                        *bind_group = created.pop();
+                    }
+                }
+            }
+        }
+        self.outdated = false;
+    }
+
+    /// Creates the bind group for drawing into the render target with the texture at `target`, a texture
+    /// can't be drawn while it's drawn into. Has to be called after `prepare`.
+    pub fn prepare_target(&mut self, device: &Device, target: u32) {
+        if self.binding == TextureBinding::Single || self.target_bind_groups.contains_key(&target) {
+            return;
+        }
+        let registered = upgrade_all(&self.slots);
+        let mut textures = vec![&self.white_pixel; self.array_len as usize];
+        for (i, (slot, tex)) in textures.iter_mut().zip(&registered).enumerate() {
+            if let (Some(tex), false) = (tex, i as u32 == target) {
+                *slot = tex;
+            }
+        }
+        let tex_layout = &self.layouts[&self.array_len];
+        let mut created = texture::create_bind_groups(
+            device,
+            tex_layout,
+            self.binding,
+            &textures,
+            &self.samplers,
+        );
+        if let Some(bind_group) = created.pop() {
+            self.target_bind_groups.insert(target, bind_group);
+        }
+    }
+
+    /// Creates the pipeline of `material` for the current textures, unless it exists already.
+    /// Has to be called after `prepare`.
+    pub fn prepare_material(
+        &mut self,
+        device: &Device,
+        material: u32,
+        shader: &wgpu::ShaderModule,
+        params_layout: &BindGroupLayout,
+    ) {
+        let key = (self.array_len, material);
+        if self.pipelines.contains_key(&key) {
+            return;
+        }
+        let tex_layout = &self.layouts[&self.array_len];
+        let pipeline_layout =
+            create_render_pipeline_layout(device, &[tex_layout, &self.cam_layout, params_layout]);
+        let pipeline =
+            create_render_pipeline(device, &pipeline_layout, shader, "fs_material", self.format);
+        self.pipelines.insert(key, pipeline);
+    }
+    /// Forgets the pipelines of a material that couldn't be created.
+    pub fn remove_material(&mut self, material: u32) {
+        self.pipelines.retain(|&(_, m), _| m != material);
+    }
+
+    /// Whether a texture is registered at `index` and fits into the binding array, sprites of other textures
+    /// don't get drawn. Has to be called after `prepare`.
+    pub fn is_drawable(&self, index: u32) -> bool {
+        let bound = self.binding == TextureBinding::Single || index < self.array_len;
+        bound && matches!(self.slots.get(index as usize), Some(Some(_)))
+    }
+
+    /// Returns the pipeline of `material` for the current textures, 0 is the default fragment shader.
+    pub fn get_pipeline(&self, material: u32) -> Option<&RenderPipeline> {
+        self.pipelines.get(&(self.array_len, material))
+    }
+
+    /// Returns the bind group with all textures for binding arrays, otherwise the one of the texture at `index`.
+    pub fn get_bind_group(&self, index: u32) -> Option<&BindGroup> {
+        let index = match self.binding {
+            TextureBinding::Array => 0,
+            TextureBinding::Single => index as usize,
+        };
+        self.bind_groups.get(index)?.as_ref()
+    }
+
+    /// Like `get_bind_group`, for drawing into the render target with the texture at `target`.
+    pub fn get_target_bind_group(&self, index: u32, target: u32) -> Option<&BindGroup> {
+        match self.binding {
+            TextureBinding::Array => self.target_bind_groups.get(&target),
+            TextureBinding::Single if index == target => None,
+            TextureBinding::Single => self.get_bind_group(index),
+        }
+    }
+
+    pub fn get_indices(&self) -> &TextureIndices {
+        &self.indices
+    }
+
+    pub fn get_binding(&self) -> TextureBinding {
+        self.binding
+    }
+}
+
+fn upgrade_all(slots: &[Option<WeakTexture>]) -> Vec<Option<Texture>> {
+    slots
+        .iter()
+        .map(|tex| tex.as_ref().and_then(WeakTexture::upgrade))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_indices_get_reused() {
+        let indices = TextureIndices::new();
+        let index = indices.next();
+        assert_eq!(index.index, ENGINE_TEXTURE_AMT);
+        drop(index);
+        assert_eq!(indices.next().index, ENGINE_TEXTURE_AMT);
+    }
+
+    #[test]
+    fn reserved_indices_are_never_handed_out() {
+        let indices = TextureIndices::new();
+        drop(indices.reserved(WHITE_PIXEL_INDEX));
+        drop(indices.reserved(GLYPH_ATLAS_INDEX));
+        assert_eq!(indices.next().index, ENGINE_TEXTURE_AMT);
+    }
+}
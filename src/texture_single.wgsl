@@ -0,0 +1,9 @@
+// only the texture of the current draw call and its sampler are bound, the indices are ignored
+@group(0) @binding(0)
+var sam: sampler;
+@group(0) @binding(1)
+var tex: texture_2d<f32>;
+
+fn sample_texture(index: u32, sampler_index: u32, tex_coords: vec2<f32>) -> vec4<f32> {
+    return textureSample(tex, sam, tex_coords);
+}
@@ -1,4 +1,4 @@
-use crate::{prelude::Vec64, time::TimeManager};
+use crate::{prelude::Vec64, texture_registry::TextureInfo, time::TimeManager};
 use cgmath::vec2;
 use egui::ClippedPrimitive;
 use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
@@ -16,19 +16,19 @@ pub struct Ui {
 impl Ui {
     pub fn update_egui_rpass(
         &mut self,
-        window: &Window,
+        window: Option<&Window>,
         config: &SurfaceConfiguration,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
     ) -> (Vec<ClippedPrimitive>, ScreenDescriptor) {
-        let full_output = self.platform.end_frame(Some(window));
+        let full_output = self.platform.end_frame(window);
         let paint_jobs = self.platform.context().tessellate(full_output.shapes);
 
         // Upload all resources for the GPU.
         let screen_descriptor = ScreenDescriptor {
             physical_width: config.width,
             physical_height: config.height,
-            scale_factor: window.scale_factor() as f32,
+            scale_factor: window.map_or(1., |window| window.scale_factor() as f32),
         };
         let tdelta: egui::TexturesDelta = full_output.textures_delta;
         self.egui_rpass
@@ -64,6 +64,7 @@ impl Ui {
         time: &TimeManager,
         target_fps: Option<u32>,
         tex_rendered: usize,
+        textures: &[TextureInfo],
     ) {
         if !self.show_engine_ui {
             return;
@@ -91,6 +92,25 @@ impl Ui {
             ui.label(format!("FPS: {:?}", fps));
             ui.label(format!("TPS: {:?}", time.get_avg_tps()));
             ui.label(format!("textures rendered this frame: {:?}", tex_rendered));
+
+            let bytes: u64 = textures.iter().map(|tex| tex.bytes).sum();
+            let header = format!(
+                "textures on the GPU: {} ({})",
+                textures.len(),
+                format_bytes(bytes)
+            );
+            egui::CollapsingHeader::new(header).show(ui, |ui| {
+                for tex in textures {
+                    ui.label(format!(
+                        "{}: {} {}x{} ({})",
+                        tex.index,
+                        tex.label,
+                        tex.width,
+                        tex.height,
+                        format_bytes(tex.bytes)
+                    ));
+                }
+            });
         });
     }
 
@@ -115,6 +135,14 @@ impl Ui {
     }
 }
 
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{bytes} B"),
+        1024..=1048575 => format!("{:.1} KiB", bytes as f64 / 1024.),
+        _ => format!("{:.1} MiB", bytes as f64 / 1048576.),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct UserUi {
     title: String,
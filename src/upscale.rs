@@ -0,0 +1,188 @@
+use cgmath::vec2;
+
+use crate::math::{rect32, Rect32, Vec32};
+
+/// A screen texture with a fixed low resolution that the scene gets rendered into, and the pass that scales
+/// it up to the frame by a whole number with black bars around it, so every pixel ends up the same size.
+pub(crate) struct Upscaler {
+    width: u32,
+    height: u32,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+impl Upscaler {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Virtual Screen Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // the default filter mode is nearest, which keeps the pixels sharp
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Virtual Screen Sampler"),
+            ..Default::default()
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("upscale_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+            ],
+            label: Some("upscale_bind_group"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("upscale.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("upscale.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Upscale Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Upscale Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_upscale",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_upscale",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            width,
+            height,
+            view,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    /// The texture the scene gets rendered into.
+    pub fn get_view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+    /// Returns the virtual resolution in pixels.
+    pub fn get_size(&self) -> Vec32 {
+        vec2(self.width as f32, self.height as f32)
+    }
+
+    /// The part of a frame of `frame_size` pixels the screen texture gets scaled into.
+    pub fn get_viewport(&self, frame_size: Vec32) -> Rect32 {
+        let size = self.get_size();
+        let fit = (frame_size.x / size.x).min(frame_size.y / size.y);
+        // Only frames smaller than the virtual resolution don't get a whole number, the scene has to fit in
+        let scale = if fit >= 1. { fit.floor() } else { fit };
+        let scaled = size * scale;
+        rect32(
+            ((frame_size.x - scaled.x) * 0.5).floor(),
+            ((frame_size.y - scaled.y) * 0.5).floor(),
+            scaled.x,
+            scaled.y,
+        )
+    }
+
+    /// Converts pixels of a frame of `frame_size` pixels to pixels of the virtual resolution.
+    pub fn frame_to_virtual(&self, pos: Vec32, frame_size: Vec32) -> Vec32 {
+        let v = self.get_viewport(frame_size);
+        let size = self.get_size();
+        vec2((pos.x - v.x) * size.x / v.w, (pos.y - v.y) * size.y / v.h)
+    }
+    /// Converts pixels of the virtual resolution to pixels of a frame of `frame_size` pixels.
+    pub fn virtual_to_frame(&self, pos: Vec32, frame_size: Vec32) -> Vec32 {
+        let v = self.get_viewport(frame_size);
+        let size = self.get_size();
+        vec2(pos.x * v.w / size.x + v.x, pos.y * v.h / size.y + v.y)
+    }
+
+    /// Scales the screen texture into `output`, which is a frame of `frame_size` pixels.
+    pub fn apply(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        frame_size: Vec32,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Upscale Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        // Rounding must not push the viewport out of the frame
+        let full_frame = rect32(0., 0., frame_size.x, frame_size.y);
+        if let Some(v) = self.get_viewport(frame_size).intersect(full_frame) {
+            if v.w > 0. && v.h > 0. {
+                render_pass.set_viewport(v.x, v.y, v.w, v.h, 0., 1.);
+                render_pass.set_pipeline(&self.pipeline);
+                render_pass.set_bind_group(0, &self.bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+        }
+    }
+}
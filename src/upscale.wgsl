@@ -0,0 +1,24 @@
+@group(0) @binding(0)
+var screen_sampler: sampler;
+@group(0) @binding(1)
+var screen: texture_2d<f32>;
+
+struct UpscaleOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// a triangle covering the viewport, which is an integer multiple of the size of the screen texture
+@vertex
+fn vs_upscale(@builtin(vertex_index) i: u32) -> UpscaleOutput {
+    let pos = vec2<f32>(f32(i == 1u) * 4. - 1., f32(i == 2u) * 4. - 1.);
+    var out: UpscaleOutput;
+    out.clip_position = vec4<f32>(pos, 0., 1.);
+    out.uv = vec2<f32>(pos.x * 0.5 + 0.5, 0.5 - pos.y * 0.5);
+    return out;
+}
+
+@fragment
+fn fs_upscale(in: UpscaleOutput) -> @location(0) vec4<f32> {
+    return textureSample(screen, screen_sampler, in.uv);
+}
@@ -1,7 +1,10 @@
+use std::ops::Range;
+
 use cgmath::{vec3, Deg, Matrix4};
 use wgpu::{util::DeviceExt, Device};
 
-use crate::prelude::{Rect32, Texture};
+use crate::prelude::{Color, DrawParams, Rect32, Texture, Vec32};
+use crate::texture_registry::WHITE_PIXEL_INDEX;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -46,17 +49,47 @@ impl TexCoords {
     }
 }
 
+/// How the fragment shader fills an instance, has to match the constants in shader.wgsl.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InstanceKind {
+    Sprite = 0,
+    Rect = 1,
+    Ellipse = 2,
+    // only the half of the quad with the corners (0, 0), (1, 0) and (0, 1)
+    Triangle = 3,
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Instance {
     model: [[f32; 2]; 3],
     index: u32,
+    color: [f32; 4],
+    kind: u32,
+    sampler_index: u32,
 }
 impl Instance {
-    pub fn new(r: Rect32, rotation: f32, index: u32) -> Self {
-        let mat4 = Matrix4::from_translation(vec3(r.x, r.y, 0.))
-            * Matrix4::from_angle_z(Deg(rotation))
-            * Matrix4::from_nonuniform_scale(r.w, r.h, 1.);
+    /// `index` and `sampler_index` are the ones of the texture.
+    pub fn new(r: Rect32, draw_params: &DrawParams, index: u32, sampler_index: u32) -> Self {
+        let (origin, scale) = (draw_params.origin, draw_params.scale);
+        let flip = |flipped: bool| if flipped { -1. } else { 1. };
+
+        // Flip the unit square around its center, so the rect stays in place
+        let flip_mat = Matrix4::from_translation(vec3(0.5, 0.5, 0.))
+            * Matrix4::from_nonuniform_scale(
+                flip(draw_params.flip_x),
+                flip(draw_params.flip_y),
+                1.,
+            )
+            * Matrix4::from_translation(vec3(-0.5, -0.5, 0.));
+
+        let pivot = vec3(r.x + origin.x * r.w, r.y + origin.y * r.h, 0.);
+        let mat4 = Matrix4::from_translation(pivot)
+            * Matrix4::from_angle_z(Deg(draw_params.rotation))
+            * Matrix4::from_nonuniform_scale(r.w * scale.x, r.h * scale.y, 1.)
+            * Matrix4::from_translation(vec3(-origin.x, -origin.y, 0.))
+            * flip_mat;
 
         let x = [mat4.x.x, mat4.x.y];
         let y = [mat4.y.x, mat4.y.y];
@@ -65,8 +98,33 @@ impl Instance {
         Self {
             model: [x, y, w],
             index,
+            color: draw_params.color.to_normalized(),
+            kind: InstanceKind::Sprite as u32,
+            sampler_index,
+        }
+    }
+    /// Creates an untextured instance that maps the unit square onto the parallelogram spanned by
+    /// `x_axis` and `y_axis` at `origin`.
+    pub fn new_shape(
+        origin: Vec32,
+        x_axis: Vec32,
+        y_axis: Vec32,
+        color: Color,
+        kind: InstanceKind,
+    ) -> Self {
+        Self {
+            model: [x_axis.into(), y_axis.into(), origin.into()],
+            // shapes don't sample their texture, but the index has to be valid
+            index: WHITE_PIXEL_INDEX,
+            color: color.to_normalized(),
+            kind: kind as u32,
+            sampler_index: 0,
         }
     }
+    /// Shapes don't sample their texture, so they can be drawn with any texture bound.
+    pub fn is_textured(&self) -> bool {
+        self.kind == InstanceKind::Sprite as u32
+    }
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         use std::mem;
         wgpu::VertexBufferLayout {
@@ -93,6 +151,21 @@ impl Instance {
                     shader_location: 8,
                     format: wgpu::VertexFormat::Uint32,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 7]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Uint32,
+                },
             ],
         }
     }
@@ -131,11 +204,29 @@ impl Vertex {
 }
 
 impl TexCoords {
+    /// Takes a rect in pixels of `tex`, relative to its region if it's part of an atlas.
     pub fn from_rect_tex(mut r: crate::math::Rect32, tex: &Texture) -> TexCoords {
-        r.x *= tex.get_inv_width();
-        r.w *= tex.get_inv_width();
-        r.y *= tex.get_inv_height();
-        r.h *= tex.get_inv_height();
+        let region = tex.get_region();
+        r.x += region.x;
+        r.y += region.y;
+        let inv_size = cgmath::vec2(tex.get_inv_width(), tex.get_inv_height());
+        Self::from_rect(r, inv_size)
+    }
+    /// Covers all of `tex`, or only its region if it's part of an atlas.
+    pub fn from_tex(tex: &Texture) -> TexCoords {
+        if tex.has_region() {
+            let size = tex.get_size();
+            Self::from_rect_tex(crate::math::rect32(0., 0., size.x, size.y), tex)
+        } else {
+            Self::default()
+        }
+    }
+    /// Takes a rect in pixels of a texture with the size 1 / `inv_size`.
+    pub fn from_rect(mut r: crate::math::Rect32, inv_size: Vec32) -> TexCoords {
+        r.x *= inv_size.x;
+        r.w *= inv_size.x;
+        r.y *= inv_size.y;
+        r.h *= inv_size.y;
 
         let b = r.y + r.h;
         let c = r.x + r.w;
@@ -178,3 +269,50 @@ pub fn create_inst_buffer(device: &Device, instance_data: &[Instance]) -> wgpu::
         usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
     })
 }
+
+/// Instances drawn with one draw call, `texture` is None if none of them is textured.
+pub struct Draw {
+    pub texture: Option<u32>,
+    pub material: u32,
+    pub range: Range<u32>,
+}
+
+/// Splits `range` of `instances` into runs that use the same material, and the same texture if `split_textures`,
+/// for drawing them with one draw call each. `material_ids` has the material of every instance. Sprites whose
+/// texture isn't `drawable` get left out.
+pub fn split_into_draws(
+    instances: &[Instance],
+    material_ids: &[u32],
+    range: Range<u32>,
+    split_textures: bool,
+    drawable: impl Fn(u32) -> bool,
+) -> Vec<Draw> {
+    let mut draws: Vec<Draw> = vec![];
+    for i in range {
+        let (inst, material) = (&instances[i as usize], material_ids[i as usize]);
+        if inst.is_textured() && !drawable(inst.index) {
+            continue;
+        }
+        // shapes can join the draw call of any texture
+        let texture = match split_textures && inst.is_textured() {
+            true => Some(inst.index),
+            false => None,
+        };
+        match draws.last_mut() {
+            Some(draw)
+                if draw.material == material
+                    && draw.range.end == i
+                    && (texture.is_none() || draw.texture.is_none() || draw.texture == texture) =>
+            {
+                draw.texture = draw.texture.or(texture);
+                draw.range.end = i + 1;
+            }
+            _ => draws.push(Draw {
+                texture,
+                material,
+                range: i..i + 1,
+            }),
+        }
+    }
+    draws
+}
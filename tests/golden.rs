@@ -0,0 +1,91 @@
+//! Runs the examples that double as golden image tests and fails if a frame differs from its reference.
+//! The examples get built first with the profile of this test, the references live in
+//! `examples/<example>/golden`. Run with `cargo test --features golden`.
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::OnceLock;
+
+// The directory of the profile this test was built with, this binary is in `<target>/<profile>/deps`
+fn profile_dir() -> PathBuf {
+    let exe = std::env::current_exe().expect("the path of the test binary is known");
+    exe.parent()
+        .and_then(|deps| deps.parent())
+        .expect("the test binary is in <target>/<profile>/deps")
+        .to_path_buf()
+}
+
+// Builds the examples once for all tests, into the directory this test was built into
+fn build_examples() -> &'static Result<(), String> {
+    static BUILT: OnceLock<Result<(), String>> = OnceLock::new();
+    BUILT.get_or_init(|| {
+        let profile_dir = profile_dir();
+        let target_dir = profile_dir
+            .parent()
+            .expect("the profile is in a target directory");
+        let profile = match profile_dir.file_name().and_then(|name| name.to_str()) {
+            Some("debug") | None => "dev",
+            Some(profile) => profile,
+        };
+
+        let output = Command::new(env!("CARGO"))
+            .args([
+                "build",
+                "--examples",
+                "--features",
+                "golden",
+                "--profile",
+                profile,
+            ])
+            .arg("--target-dir")
+            .arg(target_dir)
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .output()
+            .map_err(|e| format!("could not run cargo: {e}"))?;
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(String::from_utf8_lossy(&output.stderr).into_owned()),
+        }
+    })
+}
+
+fn run_golden(name: &str) {
+    if let Err(e) = build_examples() {
+        panic!("building the examples failed:\n{e}");
+    }
+    let path = profile_dir()
+        .join("examples")
+        .join(format!("{name}{}", std::env::consts::EXE_SUFFIX));
+
+    // the examples look for their references relative to the root of the crate
+    let output = Command::new(&path)
+        .arg("--golden")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap_or_else(|e| panic!("could not run {}: {e}", path.display()));
+    assert!(
+        output.status.success(),
+        "golden test of {name} failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn pong() {
+    run_golden("pong");
+}
+
+#[test]
+fn chess() {
+    run_golden("chess");
+}
+
+#[test]
+fn baba() {
+    run_golden("baba");
+}
+
+#[test]
+fn particle_sim() {
+    run_golden("sim");
+}